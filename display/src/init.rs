@@ -58,6 +58,9 @@ pub fn terrain_brushes() -> VecMap<Rc<Brush>> {
     ret.insert(Door as usize, Builder::new("assets/walls.png")
         .color(SADDLEBROWN).wall(128, 0, 160, 0)
         .color(LIGHTSLATEGRAY).wall(0, 0, 96, 0).finish());
+    ret.insert(LockedDoor as usize, Builder::new("assets/walls.png")
+        .color(DARKRED).wall(128, 0, 160, 0)
+        .color(LIGHTSLATEGRAY).wall(0, 0, 96, 0).finish());
     ret.insert(OpenDoor as usize, Builder::new("assets/walls.png").color(LIGHTSLATEGRAY).wall(0, 0, 96, 0).finish());
     ret.insert(Window as usize, Builder::new("assets/walls.png").color(LIGHTSLATEGRAY).wall(0, 0, 64, 0).finish());
     ret.insert(Pillar as usize, Builder::new("assets/props.png").color(GAINSBORO).tile(0, 32).finish());
@@ -91,10 +94,23 @@ pub fn entity_brushes() -> VecMap<Rc<Brush>> {
     ret.insert(Sword as usize, Builder::new("assets/props.png").color(LIGHTGRAY).tile(4*32, 1*32).finish());
     ret.insert(Helmet as usize, Builder::new("assets/props.png").color(LIGHTGRAY).tile(5*32, 1*32).finish());
     ret.insert(Armor as usize, Builder::new("assets/props.png").color(LIGHTGRAY).tile(2*32, 2*32).finish());
+    ret.insert(Ring as usize, Builder::new("assets/props.png").color(GOLD).tile(0, 2*32).finish());
+    ret.insert(Bow as usize, Builder::new("assets/props.png").color(BURLYWOOD).tile(1*32, 2*32).finish());
 
+    ret.insert(Potion as usize, Builder::new("assets/props.png").color(DEEPPINK).tile(6*32, 1*32).finish());
+    ret.insert(Ration as usize, Builder::new("assets/props.png").color(SANDYBROWN).tile(3*32, 2*32).finish());
+    ret.insert(Corpse as usize, Builder::new("assets/props.png").color(FIREBRICK).tile(6*32, 2*32).finish());
+    ret.insert(Key as usize, Builder::new("assets/props.png").color(GOLD).tile(5*32, 2*32).finish());
     ret.insert(Scroll1 as usize, Builder::new("assets/props.png").color(LIGHTYELLOW).tile(7*32, 2*32).finish());
     ret.insert(Wand1 as usize, Builder::new("assets/props.png").color(RED).tile(7*32, 1*32).finish());
     ret.insert(Wand2 as usize, Builder::new("assets/props.png").color(CYAN).tile(7*32, 1*32).finish());
+    ret.insert(Wand3 as usize, Builder::new("assets/props.png").color(PURPLE).tile(7*32, 1*32).finish());
+    ret.insert(Bag as usize, Builder::new("assets/props.png").color(BURLYWOOD).tile(3*32, 0).finish());
+    ret.insert(Chest as usize, Builder::new("assets/props.png").color(SADDLEBROWN).tile(5*32, 0).finish());
+    ret.insert(Book as usize, Builder::new("assets/props.png").color(ROYALBLUE).tile(7*32, 2*32).finish());
+    ret.insert(Trap as usize, Builder::new("assets/props.png").color(ORANGE).tile(0, 0).finish());
+    ret.insert(Torch as usize, Builder::new("assets/props.png").color(ORANGE).tile(0, 1*32).finish());
+    ret.insert(Arrow as usize, Builder::new("assets/props.png").color(WHEAT).tile(1*32, 2*32).finish());
     ret
 }
 
@@ -173,6 +173,7 @@ impl WorldView {
             let (mobs, items): (Vec<Entity>, Vec<Entity>) = world
                 .entities_at(loc)
                 .into_iter()
+                .filter(|&e| !world.is_hidden(e))
                 .partition(|&e| world.is_mob(e));
 
             // Draw non-mob entities, these are static and shown in map memory
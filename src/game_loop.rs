@@ -1,5 +1,6 @@
 use calx::Dir6;
 use calx::{color, Rgba};
+use calx_ecs::Entity;
 use display::{self, Backend, Core};
 use euclid::{Point2D, Rect};
 use glium::glutin::ElementState;
@@ -8,12 +9,16 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::rc::Rc;
 use vitral::{Align, FontData, RectUtil};
-use world::{Command, CommandResult, Event, ItemType, Location, Mutate, Query, Slot, World};
+use world::{CommandResult, Event, ItemType, Location, Mutate, Query, Recorder, Slot, World};
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum State {
     Main,
     Inventory(InventoryAction),
+    /// Choosing which item to pick up from a pile.
+    PickUp,
+    /// Choosing which container to put the item in slot in.
+    PutIn(Slot),
     Console,
     Aim(AimAction),
 }
@@ -21,6 +26,11 @@ enum State {
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum InventoryAction {
     Drop,
+    /// Drop a single item off a stack.
+    DropOne,
+    /// Put an item in a container.
+    PutIn,
+    Throw,
     Equip,
     Use,
 }
@@ -28,16 +38,31 @@ enum InventoryAction {
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 enum AimAction {
     Zap(Slot),
+    Throw(Slot),
+    Cast(Slot),
+    Fire,
+    Open,
+    Close,
     // Maybe add intrinsic abilities not tied to a specific entity later
 }
 
+/// Movement that continues over multiple turns without player input.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum AutoMove {
+    Explore,
+    Travel(Location),
+}
+
 pub struct GameLoop {
     core: Core,
     font: Rc<FontData>,
-    pub world: World,
+    pub world: Recorder,
     pub console: display::Console,
     camera_loc: Location,
     state: State,
+    auto_move: Option<AutoMove>,
+    /// Map location under the mouse cursor.
+    cursor_loc: Option<Location>,
 }
 
 enum Side {
@@ -46,7 +71,7 @@ enum Side {
 }
 
 impl GameLoop {
-    pub fn new(backend: &mut Backend, world: World) -> GameLoop {
+    pub fn new(backend: &mut Backend, world: Recorder) -> GameLoop {
         let font = display::font();
         GameLoop {
             core: backend.new_core(),
@@ -55,6 +80,8 @@ impl GameLoop {
             console: display::Console::new(font),
             camera_loc: Location::new(0, 0, 0),
             state: State::Main,
+            auto_move: None,
+            cursor_loc: None,
         }
     }
 
@@ -62,7 +89,7 @@ impl GameLoop {
     fn smart_step(&mut self, dir: Dir6) -> CommandResult {
         let player = self.world.player().ok_or(())?;
         let loc = self.world.location(player).ok_or(())?;
-        let destination = loc.jump(&self.world, dir);
+        let destination = loc.jump(&*self.world, dir);
 
         if let Some(mob) = self.world.mob_at(destination) {
             if self.world.is_hostile_to(player, mob) {
@@ -123,21 +150,75 @@ impl GameLoop {
                 self.enter_state(State::Inventory(InventoryAction::Drop));
                 Ok(Vec::new())
             }
+            V => {
+                self.enter_state(State::Inventory(InventoryAction::DropOne));
+                Ok(Vec::new())
+            }
+            P => {
+                self.enter_state(State::Inventory(InventoryAction::PutIn));
+                Ok(Vec::new())
+            }
+            T => {
+                self.enter_state(State::Inventory(InventoryAction::Throw));
+                Ok(Vec::new())
+            }
             U => {
                 self.enter_state(State::Inventory(InventoryAction::Use));
                 Ok(Vec::new())
             }
-            G => self.world.take(),
+            G => self.pick_up(),
+            Num1 | Num2 | Num3 | Num4 | Num5 | Num6 | Num7 | Num8 => {
+                let slot = SLOT_DATA.iter().find(|s| s.code == scancode).ok_or(())?.slot;
+                self.cast(slot)
+            }
+            F => {
+                self.enter_state(State::Aim(AimAction::Fire));
+                Ok(Vec::new())
+            }
+            O => {
+                self.enter_state(State::Aim(AimAction::Open));
+                Ok(Vec::new())
+            }
+            C => {
+                self.enter_state(State::Aim(AimAction::Close));
+                Ok(Vec::new())
+            }
+            X => {
+                self.auto_move = Some(AutoMove::Explore);
+                Ok(Vec::new())
+            }
+            Enter => {
+                self.auto_move = self.cursor_loc.map(AutoMove::Travel);
+                Ok(Vec::new())
+            }
             Space | Pad5 => self.world.pass(),
+            // S is taken by movement.
+            R => self.world.search(),
             F5 => {
                 self.world
                     .save(&mut File::create("save.gam").unwrap())
                     .unwrap();
                 Ok(Vec::new())
             }
+            F6 => {
+                if let Some(replay) = self.world.replay() {
+                    replay
+                        .save(&mut File::create("replay.ron").unwrap())
+                        .unwrap();
+                } else {
+                    let _ = writeln!(&mut self.console, "No replay recorded for this game");
+                }
+                Ok(Vec::new())
+            }
             F9 => {
                 let mut savefile = File::open("save.gam").unwrap();
-                self.world = World::load(&mut savefile).unwrap();
+                match World::load(&mut savefile) {
+                    // Saves don't carry command history, the loaded game won't be recorded.
+                    Ok(world) => self.world = Recorder::from_world(world),
+                    Err(e) => {
+                        let _ = writeln!(&mut self.console, "{}", e);
+                    }
+                }
                 Ok(Vec::new())
             }
             F12 => {
@@ -148,21 +229,42 @@ impl GameLoop {
         }
     }
 
-    fn zap(&mut self, slot: Slot, dir: Dir6) -> CommandResult {
-        let ret = self.world.zap_item(slot, dir)?;
+    fn aim(&mut self, action: AimAction, dir: Dir6) -> CommandResult {
+        let ret = match action {
+            AimAction::Zap(slot) => self.world.zap_item(slot, dir)?,
+            AimAction::Throw(slot) => self.world.throw(slot, dir)?,
+            AimAction::Cast(slot) => self.world.cast(slot, Some(dir))?,
+            AimAction::Fire => self.world.fire(dir)?,
+            AimAction::Open => self.world.open(dir)?,
+            AimAction::Close => self.world.close(dir)?,
+        };
         self.enter_state(State::Main);
         Ok(ret)
     }
 
-    fn aim_input(&mut self, slot: Slot, scancode: Scancode) -> CommandResult {
+    /// Cast the spell in a spell slot, aiming it first if needed.
+    fn cast(&mut self, slot: Slot) -> CommandResult {
+        let player = self.world.player().ok_or(())?;
+        let spell = self.world.entity_equipped(player, slot).ok_or(())?;
+        match self.world.item_type(spell) {
+            Some(ItemType::Spell(effect)) if effect.is_directed() => {
+                self.enter_state(State::Aim(AimAction::Cast(slot)));
+                Ok(Vec::new())
+            }
+            Some(ItemType::Spell(_)) => self.world.cast(slot, None),
+            _ => Err(()),
+        }
+    }
+
+    fn aim_input(&mut self, action: AimAction, scancode: Scancode) -> CommandResult {
         use scancode::Scancode::*;
         match scancode {
-            Q => self.zap(slot, Dir6::Northwest),
-            W => self.zap(slot, Dir6::North),
-            E => self.zap(slot, Dir6::Northeast),
-            A => self.zap(slot, Dir6::Southwest),
-            S => self.zap(slot, Dir6::South),
-            D => self.zap(slot, Dir6::Southeast),
+            Q => self.aim(action, Dir6::Northwest),
+            W => self.aim(action, Dir6::North),
+            E => self.aim(action, Dir6::Northeast),
+            A => self.aim(action, Dir6::Southwest),
+            S => self.aim(action, Dir6::South),
+            D => self.aim(action, Dir6::Southeast),
             Escape => {
                 self.enter_state(State::Main);
                 Ok(Vec::new())
@@ -207,14 +309,41 @@ impl GameLoop {
 
     fn inventory_action(&mut self, slot: Slot, action: InventoryAction) -> CommandResult {
         match action {
-            InventoryAction::Drop => {
-                let ret = self.world.drop(slot);
+            InventoryAction::Drop | InventoryAction::DropOne => {
+                let ret = if action == InventoryAction::DropOne {
+                    self.world.drop_some(slot, 1)
+                } else {
+                    self.world.drop(slot)
+                };
                 // After succesful drop, go back to main state.
                 if ret.is_ok() {
                     self.enter_state(State::Main);
                 }
                 ret
             }
+            InventoryAction::Throw => {
+                let player = self.world.player().ok_or(())?;
+                self.world.entity_equipped(player, slot).ok_or(())?;
+                self.enter_state(State::Aim(AimAction::Throw(slot)));
+                Ok(Vec::new())
+            }
+            InventoryAction::PutIn => {
+                let containers = self.put_in_targets(slot);
+                match containers.len() {
+                    0 => Err(()),
+                    1 => {
+                        let ret = self.world.put_in(slot, containers[0]);
+                        if ret.is_ok() {
+                            self.enter_state(State::Main);
+                        }
+                        ret
+                    }
+                    _ => {
+                        self.enter_state(State::PutIn(slot));
+                        Ok(Vec::new())
+                    }
+                }
+            }
             // Can equip multiple items in one go, wait for ESC to return to main state.
             InventoryAction::Equip => self.world.equip(slot),
             InventoryAction::Use => {
@@ -222,7 +351,7 @@ impl GameLoop {
 
                 if let Some(item) = self.world.entity_equipped(player, slot) {
                     match self.world.item_type(item) {
-                        Some(ItemType::UntargetedUsable(_)) => {
+                        Some(ItemType::UntargetedUsable(_)) | Some(ItemType::Spellbook(_)) => {
                             let ret = self.world.use_item(slot)?;
                             self.enter_state(State::Main);
                             return Ok(ret);
@@ -240,6 +369,91 @@ impl GameLoop {
         }
     }
 
+    /// Items on the floor under the player and inside containers within reach.
+    fn floor_items(&self) -> Vec<Entity> {
+        self.world
+            .player()
+            .map_or_else(Vec::new, |p| self.world.reachable_items(p))
+    }
+
+    /// Containers within reach that the item in slot can go in.
+    fn put_in_targets(&self, slot: Slot) -> Vec<Entity> {
+        let player = match self.world.player() {
+            Some(player) => player,
+            None => return Vec::new(),
+        };
+        let item = match self.world.entity_equipped(player, slot) {
+            Some(item) => item,
+            None => return Vec::new(),
+        };
+
+        self.world
+            .reachable_containers(player)
+            .into_iter()
+            .filter(|&c| c != item && !self.world.entity_contains(item, c))
+            .collect()
+    }
+
+    /// Take an item from the floor or out of a container.
+    fn take_floor_item(&mut self, item: Entity) -> CommandResult {
+        if self.world.entity_container(item).is_some() {
+            self.world.take_out(item)
+        } else {
+            self.world.take(item)
+        }
+    }
+
+    /// Take an item from the floor, bring up a menu if there are several to choose from.
+    fn pick_up(&mut self) -> CommandResult {
+        let items = self.floor_items();
+        match items.len() {
+            0 => Err(()),
+            1 => self.take_floor_item(items[0]),
+            _ => {
+                self.enter_state(State::PickUp);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    fn pick_up_input(&mut self, scancode: Scancode) -> CommandResult {
+        if scancode == Scancode::Escape {
+            self.enter_state(State::Main);
+            return Ok(Vec::new());
+        }
+
+        let items = self.floor_items();
+        if let Some(idx) = MENU_KEYS.iter().position(|&(_, code)| code == scancode) {
+            if let Some(&item) = items.get(idx) {
+                let ret = self.take_floor_item(item);
+                if ret.is_ok() {
+                    self.enter_state(State::Main);
+                }
+                return ret;
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn put_in_input(&mut self, slot: Slot, scancode: Scancode) -> CommandResult {
+        if scancode == Scancode::Escape {
+            self.enter_state(State::Main);
+            return Ok(Vec::new());
+        }
+
+        let containers = self.put_in_targets(slot);
+        if let Some(idx) = MENU_KEYS.iter().position(|&(_, code)| code == scancode) {
+            if let Some(&container) = containers.get(idx) {
+                let ret = self.world.put_in(slot, container);
+                if ret.is_ok() {
+                    self.enter_state(State::Main);
+                }
+                return ret;
+            }
+        }
+        Ok(Vec::new())
+    }
+
     fn console_input(&mut self, scancode: Scancode) -> CommandResult {
         use scancode::Scancode::*;
         match scancode {
@@ -259,6 +473,70 @@ impl GameLoop {
         }
     }
 
+    /// Return whether the player can see hostile mobs.
+    fn sees_enemies(&self) -> bool {
+        let player = match self.world.player() {
+            Some(p) => p,
+            None => return false,
+        };
+
+        self.world.entities().any(|&e| {
+            self.world.is_npc(e) && self.world.is_hostile_to(e, player)
+                && self.world
+                    .location(e)
+                    .map_or(false, |loc| self.world.player_sees(loc))
+        })
+    }
+
+    /// Take the next step of an ongoing automatic move.
+    fn auto_move_step(&mut self) {
+        let auto_move = match self.auto_move {
+            Some(m) => m,
+            None => return,
+        };
+
+        // Stop when things get dangerous.
+        if self.sees_enemies() {
+            self.auto_move = None;
+            return;
+        }
+
+        let ret = match auto_move {
+            AutoMove::Explore => self.world.explore(),
+            AutoMove::Travel(destination) => {
+                let player_loc = self.world.player().and_then(|p| self.world.location(p));
+                if player_loc == Some(destination) {
+                    Err(())
+                } else {
+                    self.world.travel(destination)
+                }
+            }
+        };
+
+        match ret {
+            Ok(events) => self.process_events(events),
+            Err(_) => self.auto_move = None,
+        }
+    }
+
+    /// Convert world events into UI display effects.
+    fn process_events(&mut self, events: Vec<Event>) {
+        for e in events {
+            match e {
+                Event::Msg(text) => {
+                    let _ = writeln!(&mut self.console, "{}", text);
+                }
+                Event::Damage { entity, amount } => {
+                    let name = self.world.entity_name(entity);
+                    // TODO: Use graphical effect
+                    let _ = writeln!(&mut self.console, "{} dmg {}", name, amount);
+                }
+                // TODO: Animations for the rest of the events.
+                _ => {}
+            }
+        }
+    }
+
     fn todo(&mut self) {
         // TODO: Bring back some debug commands
     }
@@ -313,6 +591,58 @@ impl GameLoop {
         Ok(())
     }
 
+    fn draw_pick_up(&mut self) {
+        let names: Vec<String> = self
+            .floor_items()
+            .into_iter()
+            .map(|item| {
+                let name = self.world.entity_name(item);
+                if let Some(container) = self.world.entity_container(item) {
+                    format!("{} (in {})", name, self.world.entity_name(container))
+                } else {
+                    name
+                }
+            })
+            .collect();
+        self.draw_menu(&names);
+    }
+
+    fn draw_put_in(&mut self, slot: Slot) {
+        let names: Vec<String> = self
+            .put_in_targets(slot)
+            .into_iter()
+            .map(|c| self.world.entity_name(c))
+            .collect();
+        self.draw_menu(&names);
+    }
+
+    /// Draw a list of choices keyed by letters.
+    fn draw_menu(&mut self, entries: &[String]) {
+        let bounds = self.core.bounds();
+        self.core.fill_rect(&bounds, [0.0, 0.0, 0.0, 0.99]);
+
+        let mut letter_pos = Point2D::new(0.0, 0.0);
+        let mut item_name_pos = Point2D::new(20.0, 0.0);
+        let text_color = [1.0, 1.0, 1.0, 1.0];
+
+        for (&(key, _), name) in MENU_KEYS.iter().zip(entries) {
+            letter_pos = self.core.draw_text(
+                &*self.font,
+                letter_pos,
+                Align::Left,
+                text_color,
+                &format!("{})", key),
+            );
+            item_name_pos = self.core.draw_text(
+                &*self.font,
+                item_name_pos,
+                Align::Left,
+                text_color,
+                name,
+            );
+        }
+    }
+
     pub fn status_draw(&mut self, area: &Rect<f32>) {
         self.core.fill_rect(area, Rgba::from(0x33_11_11_ff).into());
         self.core.draw_text(
@@ -341,6 +671,7 @@ impl GameLoop {
 
         self.core.set_clip(view_area);
         view.draw(&self.world, &mut self.core);
+        self.cursor_loc = view.cursor_loc;
         self.core.clear_clip();
 
         self.core.set_clip(status_area);
@@ -351,6 +682,8 @@ impl GameLoop {
             State::Inventory(_) => {
                 let _ = self.draw_inventory();
             }
+            State::PickUp => self.draw_pick_up(),
+            State::PutIn(slot) => self.draw_put_in(slot),
             State::Console => {
                 let mut console_area = screen_area;
                 console_area.size.height = 184.0;
@@ -366,32 +699,25 @@ impl GameLoop {
         // TODO FIXME: Needs to be written better, need kb interrupts outside player input phase...
         if let Some(event) = backend.poll_key() {
             if event.state == ElementState::Pressed {
+                // Any key interrupts automatic movement.
+                self.auto_move = None;
+
                 let scancode_adjust = if cfg!(target_os = "linux") { 8 } else { 0 };
                 if let Some(scancode) =
                     Scancode::new((event.scancode as i32 + scancode_adjust) as u8)
                 {
                     let ret = match self.state {
                         State::Inventory(_) => self.inventory_input(scancode),
+                        State::PickUp => self.pick_up_input(scancode),
+                        State::PutIn(slot) => self.put_in_input(slot, scancode),
                         State::Console => self.console_input(scancode),
-                        State::Aim(AimAction::Zap(slot)) => self.aim_input(slot, scancode),
+                        State::Aim(action) => self.aim_input(action, scancode),
                         _ => self.game_input(backend, scancode),
                     };
 
                     if let Ok(events) = ret {
                         // Input event caused a successful world step and we got an event sequence out.
-                        // Convert events into UI display effects.
-                        for e in events {
-                            match e {
-                                Event::Msg(text) => {
-                                    let _ = writeln!(&mut self.console, "{}", text);
-                                }
-                                Event::Damage { entity, amount } => {
-                                    let name = self.world.entity_name(entity);
-                                    // TODO: Use graphical effect
-                                    let _ = writeln!(&mut self.console, "{} dmg {}", name, amount);
-                                }
-                            }
-                        }
+                        self.process_events(events);
                     }
                 }
             }
@@ -399,6 +725,7 @@ impl GameLoop {
 
         if self.world.player_can_act() {
             self.world.tick_anims();
+            self.auto_move_step();
         } else {
             // When playing turn-based and running the animations between player's inputs, speed
             // things up so that the pace feels snappy.
@@ -408,8 +735,9 @@ impl GameLoop {
                 if self.world.player_can_act() {
                     break;
                 }
-                // TODO FIXME process events in return value.
-                let _ = self.world.next_tick();
+                if let Ok(events) = self.world.next_tick() {
+                    self.process_events(events);
+                }
             }
         }
 
@@ -461,3 +789,15 @@ static SLOT_DATA: [SlotData; 34] = [
     SlotData { key: 'y', code: Scancode::Y,    slot: Slot::InventoryY, name: "" },
     SlotData { key: 'z', code: Scancode::Z,    slot: Slot::InventoryZ, name: "" },
 ];
+
+/// Keys for picking entries from a list menu.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+static MENU_KEYS: [(char, Scancode); 26] = [
+    ('a', Scancode::A), ('b', Scancode::B), ('c', Scancode::C), ('d', Scancode::D),
+    ('e', Scancode::E), ('f', Scancode::F), ('g', Scancode::G), ('h', Scancode::H),
+    ('i', Scancode::I), ('j', Scancode::J), ('k', Scancode::K), ('l', Scancode::L),
+    ('m', Scancode::M), ('n', Scancode::N), ('o', Scancode::O), ('p', Scancode::P),
+    ('q', Scancode::Q), ('r', Scancode::R), ('s', Scancode::S), ('t', Scancode::T),
+    ('u', Scancode::U), ('v', Scancode::V), ('w', Scancode::W), ('x', Scancode::X),
+    ('y', Scancode::Y), ('z', Scancode::Z),
+];
@@ -3,6 +3,7 @@
 
 #[macro_use]
 extern crate calx;
+extern crate calx_ecs;
 extern crate display;
 extern crate env_logger;
 extern crate euclid;
@@ -18,9 +19,11 @@ use calx::TimestepLoop;
 use display::Backend;
 use game_loop::GameLoop;
 use rand::Rng;
+use std::env;
+use std::fs::File;
 use std::thread;
 use std::time::Duration;
-use world::World;
+use world::{Recorder, Replay};
 
 pub fn main() {
     env_logger::init();
@@ -31,11 +34,20 @@ pub fn main() {
 
     let mut backend = Backend::start(640, 360, "Magog").expect("Failed to start rendering backend");
 
-    let seed = rand::thread_rng().gen();
-    // Print out the seed in case worldgen has a bug and we want to debug stuff with the same seed.
-    println!("Seed: {}", seed);
+    let world = if let Some(path) = env::args().nth(1) {
+        // Continue from a recorded replay file.
+        let replay = Replay::load(&mut File::open(&path).expect("Failed to open replay"))
+            .expect("Failed to read replay");
+        println!("Replaying {} with seed {}", path, replay.seed);
+        Recorder::from_replay(replay).expect("Failed to run replay")
+    } else {
+        let seed = rand::thread_rng().gen();
+        // Print out the seed in case worldgen has a bug and we want to debug stuff with the same seed.
+        println!("Seed: {}", seed);
+        Recorder::new(seed)
+    };
 
-    let mut game = GameLoop::new(&mut backend, World::new(seed));
+    let mut game = GameLoop::new(&mut backend, world);
 
     'gameloop: loop {
         while timestep.should_update() {
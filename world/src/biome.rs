@@ -1,12 +1,13 @@
-use calx::{self, RngExt, WeightedChoice};
+use calx::{self, CellVector, RngExt, WeightedChoice};
 use location::{Location, Sector};
 use map::Map;
 use rand::seq;
 use rand::Rng as _Rng;
-use spec::{self, EntitySpawn};
+use spec::{self, EntitySpawn, Spec};
 use std::error::Error;
 use std::str::FromStr;
 use std::sync::Arc;
+use terrain::Terrain;
 use vaults;
 use {Distribution, Rng};
 
@@ -89,22 +90,50 @@ impl Distribution<Dungeon> for Biome {
             let room = rng.pick_slice(&vaults::EXITS).unwrap();
             gen.place_room(rng, &*room)?;
 
-            if let Some(map) = gen.join_disjoint_regions(rng) {
-                Ok(map)
+            let mut map = if let Some(map) = gen.join_disjoint_regions(rng) {
+                map
             } else {
                 die!("Failed to join map");
+            };
+
+            // Fill treasure spots with random items.
+            for pos in map.treasure_sites() {
+                let count = map[pos].treasure;
+                for _ in 0..count {
+                    let item: Treasure = biome.sample(rng);
+                    map.push_spawn(pos, item.0);
+                }
             }
+
+            // Put a key for every locked door somewhere it can be reached from without keys.
+            let num_keys = map.locked_doors().len();
+            if num_keys > 0 {
+                let sites: Vec<CellVector> = map
+                    .unlocked_region(map.entrances()[0])
+                    .into_iter()
+                    .filter(|&p| map[p].terrain == Terrain::Ground)
+                    .collect();
+                if sites.len() < num_keys {
+                    die!("No room for keys");
+                }
+                for pos in seq::sample_slice(rng, &sites, num_keys) {
+                    map.push_spawn(pos, EntitySpawn::from_str("key").unwrap());
+                }
+            }
+
+            Ok(map)
         }
 
         Dungeon(calx::retry_gen(16, rng, |rng| gen(rng, self)).expect("Couldn't generate map"))
     }
 }
 
-impl Distribution<EntitySpawn> for Biome {
-    fn sample(&self, rng: &mut Rng) -> EntitySpawn {
+impl Biome {
+    /// Pick a random spec that can spawn in the biome and passes the filter.
+    fn sample_spec(&self, rng: &mut Rng, filter: impl Fn(&dyn Spec) -> bool) -> EntitySpawn {
         let item = spec::iter_specs()
             .weighted_choice(rng, |item| {
-                if item.rarity() == 0.0 || item.min_depth() > self.depth {
+                if item.rarity() == 0.0 || item.min_depth() > self.depth || !filter(&**item) {
                     0.0
                 } else {
                     1.0 / item.rarity()
@@ -115,3 +144,14 @@ impl Distribution<EntitySpawn> for Biome {
         EntitySpawn::from_str(item.name()).unwrap()
     }
 }
+
+impl Distribution<EntitySpawn> for Biome {
+    fn sample(&self, rng: &mut Rng) -> EntitySpawn { self.sample_spec(rng, |_| true) }
+}
+
+/// Random item spawn for treasure spots in vaults.
+struct Treasure(EntitySpawn);
+
+impl Distribution<Treasure> for Biome {
+    fn sample(&self, rng: &mut Rng) -> Treasure { Treasure(self.sample_spec(rng, |s| s.is_item())) }
+}
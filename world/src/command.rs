@@ -1,6 +1,8 @@
 use calx::Dir6;
+use calx_ecs::Entity;
 use event::Event;
-use item::Slot;
+use item::{ItemType, Slot};
+use location::Location;
 use mutate::Mutate;
 
 pub type CommandResult = Result<Vec<Event>, ()>;
@@ -29,6 +31,54 @@ pub trait Command: Mutate + Sized {
         self.next_tick()
     }
 
+    /// Step towards the nearest unexplored location.
+    ///
+    /// Will fail if there is nothing left to explore nearby.
+    fn explore(&mut self) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        let dir = self.explore_dir(player).ok_or(())?;
+        self.step(dir)
+    }
+
+    /// Step along a path towards a destination.
+    ///
+    /// Will fail if no path can be found.
+    fn travel(&mut self, destination: Location) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        let dir = self.pathing_dir_towards(player, destination).ok_or(())?;
+        self.step(dir)
+    }
+
+    /// The player fires the equipped ranged weapon in direction.
+    ///
+    /// Will fail if the player has no ranged attack.
+    fn fire(&mut self, dir: Dir6) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        self.entity_fire(player, dir)?;
+        self.next_tick()
+    }
+
+    /// The player opens a door in direction.
+    ///
+    /// Will fail if there is no closed door there, or if the door is locked and the player has no
+    /// key.
+    fn open(&mut self, dir: Dir6) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        let loc = self.location(player).ok_or(())?.jump(self, dir);
+        self.open_door(player, loc)?;
+        self.next_tick()
+    }
+
+    /// The player closes a door in direction.
+    ///
+    /// Will fail if there is no open door there or if the doorway is blocked.
+    fn close(&mut self, dir: Dir6) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        let loc = self.location(player).ok_or(())?.jump(self, dir);
+        self.close_door(player, loc)?;
+        self.next_tick()
+    }
+
     /// Pass a turn without action from the player.
     ///
     /// Will usually succeed, but some games might not let the player pass turns.
@@ -39,14 +89,20 @@ pub trait Command: Mutate + Sized {
         self.next_tick()
     }
 
-    /// Take item from floor
+    /// Spend a turn looking for hidden traps nearby.
+    fn search(&mut self) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        self.entity_search(player)?;
+        self.next_tick()
+    }
+
+    /// Take an item from the floor the player is standing on.
     ///
-    /// No selection support yet for multiple items, you pick up the topmost one.
-    /// Try to maintain a convention where there's no more than one item in a single location.
-    fn take(&mut self) -> CommandResult {
+    /// Will fail if the item isn't at the player's location.
+    fn take(&mut self, item: Entity) -> CommandResult {
         let player = self.player().ok_or(())?;
         let location = self.location(player).ok_or(())?;
-        if let Some(item) = self.item_at(location) {
+        if self.items_at(location).contains(&item) {
             self.entity_take(player, item)?;
             self.next_tick()
         } else {
@@ -56,27 +112,99 @@ pub trait Command: Mutate + Sized {
 
     /// Drop item held in slot.
     fn drop(&mut self, slot: Slot) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        let item = self.entity_equipped(player, slot).ok_or(())?;
+        let count = self.item_count(item);
+        self.drop_some(slot, count)
+    }
+
+    /// Drop some of a stack of items held in slot.
+    fn drop_some(&mut self, slot: Slot, count: u32) -> CommandResult {
+        if slot.is_spell_slot() {
+            return Err(());
+        }
         let player = self.player().ok_or(())?;
         let location = self.location(player).ok_or(())?;
         if let Some(item) = self.entity_equipped(player, slot) {
-            self.place_entity(item, location);
+            if count == 0 || count > self.item_count(item) {
+                return Err(());
+            }
+            if slot.is_equipment_slot() && self.is_stuck_with(player, item) {
+                return self.next_tick();
+            }
+            let item = if count < self.item_count(item) {
+                self.split_stack(item, count, location).ok_or(())?
+            } else {
+                self.place_entity(item, location);
+                item
+            };
+            self.emit(Event::Drop {
+                entity: player,
+                item,
+            });
+            if slot.is_equipment_slot() {
+                self.rebuild_stats(player);
+            }
             self.next_tick()
         } else {
             Err(())
         }
     }
 
+    /// Throw the item held in slot.
+    fn throw(&mut self, slot: Slot, dir: Dir6) -> CommandResult {
+        if slot.is_spell_slot() {
+            return Err(());
+        }
+        let player = self.player().ok_or(())?;
+        let item = self.entity_equipped(player, slot).ok_or(())?;
+        if slot.is_equipment_slot() && self.is_stuck_with(player, item) {
+            return self.next_tick();
+        }
+        self.entity_throw(player, item, dir)?;
+        self.next_tick()
+    }
+
+    /// Put item held in slot into a container.
+    fn put_in(&mut self, slot: Slot, container: Entity) -> CommandResult {
+        if slot.is_spell_slot() {
+            return Err(());
+        }
+        let player = self.player().ok_or(())?;
+        let item = self.entity_equipped(player, slot).ok_or(())?;
+        if slot.is_equipment_slot() && self.is_stuck_with(player, item) {
+            return self.next_tick();
+        }
+        self.entity_put_in(player, item, container)?;
+        self.next_tick()
+    }
+
+    /// Take an item out of a container within reach.
+    fn take_out(&mut self, item: Entity) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        self.entity_take_out(player, item)?;
+        self.next_tick()
+    }
+
     /// Swap item between equipment and inventory slots
     ///
     /// Behavior depends on slot. Equipment slots go to inventory, inventory slots go to equip. The
     /// item will be moved to the first available slot.
     fn equip(&mut self, slot: Slot) -> CommandResult {
+        if slot.is_spell_slot() {
+            return Err(());
+        }
         let player = self.player().ok_or(())?;
+        if !self.can_manipulate(player) {
+            return Err(());
+        }
         let item = self.entity_equipped(player, slot).ok_or(())?;
 
         let swap_slot = if slot.is_equipment_slot() {
             // Remove equipped.
-            // TODO: Items that can't be removed because of curses etc. trip here.
+            if self.is_stuck_with(player, item) {
+                return self.next_tick();
+            }
             self.free_bag_slot(player).ok_or(())?
         } else {
             // Equip from bag.
@@ -85,6 +213,19 @@ pub trait Command: Mutate + Sized {
         };
 
         self.equip_item(item, player, swap_slot);
+        if slot.is_equipment_slot() {
+            self.emit(Event::Unequip {
+                entity: player,
+                item,
+            });
+        } else {
+            self.emit(Event::Equip {
+                entity: player,
+                item,
+            });
+            // The curse takes hold as soon as the item is put on.
+            self.reveal_curse(item);
+        }
         self.next_tick()
     }
 
@@ -93,8 +234,21 @@ pub trait Command: Mutate + Sized {
         let player = self.player().ok_or(())?;
         let location = self.location(player).ok_or(())?;
         let item = self.entity_equipped(player, slot).ok_or(())?;
+        match self.item_type(item) {
+            Some(ItemType::Food(_)) => {
+                self.entity_eat(player, item)?;
+                return self.next_tick();
+            }
+            Some(ItemType::Spellbook(_)) => {
+                self.entity_learn_spell(player, item)?;
+                return self.next_tick();
+            }
+            _ => {}
+        }
+
         if self.uses_left(item) > 0 {
             self.cast_spell(location, item, Some(player))?;
+            self.identify(item);
             self.drain_charge(item);
         } else {
             msg!(self, "Nothing happens.").send();
@@ -108,6 +262,250 @@ pub trait Command: Mutate + Sized {
         let location = self.location(player).ok_or(())?;
         let item = self.entity_equipped(player, slot).ok_or(())?;
         self.cast_directed_spell(location, dir, item, Some(player))?;
+        self.identify(item);
         self.next_tick()
     }
+
+    /// Cast a known spell, aiming it if it's a directed one.
+    fn cast(&mut self, slot: Slot, dir: Option<Dir6>) -> CommandResult {
+        let player = self.player().ok_or(())?;
+        let spell = self.entity_equipped(player, slot).ok_or(())?;
+        self.entity_cast(player, spell, dir)?;
+        self.next_tick()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Command;
+    use calx::Dir6;
+    use calx_ecs::Entity;
+    use event::Event;
+    use item::{ItemType, MagicEffect, Slot};
+    use mutate::Mutate;
+    use query::Query;
+    use stats::Intrinsic;
+    use terraform::{Terraform, TerrainQuery};
+    use terrain::Terrain;
+    use test_util::{give, new_world, slot_of, spawn};
+    use world::World;
+
+    /// Turn the entity into something that can't handle objects.
+    fn lose_hands(world: &mut World, e: Entity) {
+        world.ecs_mut().stats[e].base.intrinsics &= !(1 << Intrinsic::Hands as u32);
+        world.rebuild_stats(e);
+        assert!(!world.can_manipulate(e));
+    }
+
+    #[test]
+    fn test_item_stacking() {
+        let (mut world, player, loc) = new_world();
+        for _ in 0..3 {
+            give(&mut world, "scroll of lightning");
+        }
+
+        // All three went in the same bag slot.
+        let stacks: Vec<_> = world
+            .entities_in(player)
+            .into_iter()
+            .filter(|&e| world.spawn_name(e) == Some("scroll of lightning"))
+            .collect();
+        assert_eq!(stacks.len(), 1);
+        let stack = stacks[0];
+        assert_eq!(world.item_count(stack), 3);
+        let slot = slot_of(&world, player, stack);
+
+        world.drop_some(slot, 1).unwrap();
+        assert_eq!(world.item_count(stack), 2);
+        let dropped = world.item_at(loc).unwrap();
+        assert_ne!(dropped, stack);
+        assert_eq!(world.item_count(dropped), 1);
+
+        assert!(world.drop_some(slot, 3).is_err());
+        world.drop(slot).unwrap();
+        assert!(world.entity_equipped(player, slot).is_none());
+
+        // Ammo piles up too.
+        for _ in 0..2 {
+            give(&mut world, "arrow");
+        }
+        let arrows: Vec<_> = world
+            .entities_in(player)
+            .into_iter()
+            .filter(|&e| world.spawn_name(e) == Some("arrow"))
+            .collect();
+        assert_eq!(arrows.len(), 1);
+        assert_eq!(world.item_count(arrows[0]), 2);
+    }
+
+    #[test]
+    fn test_take_selected_item() {
+        let (mut world, player, loc) = new_world();
+        let items: Vec<_> = ["potion of healing", "scroll of lightning", "ration"]
+            .iter()
+            .map(|name| spawn(&mut world, name, loc))
+            .collect();
+
+        // The pile is listed in a fixed order.
+        let mut pile = items.clone();
+        pile.sort();
+        assert_eq!(world.items_at(loc), pile);
+
+        // Take the one from the middle of the pile.
+        let events = world.take(items[1]).unwrap();
+        assert!(world.entity_contains(player, items[1]));
+        assert!(!world.entity_contains(player, items[0]));
+        pile.retain(|&e| e != items[1]);
+        assert_eq!(world.items_at(loc), pile);
+        assert!(events.iter().any(|e| match *e {
+            Event::PickUp { item, .. } => item == items[1],
+            _ => false,
+        }));
+
+        // Items elsewhere can't be taken.
+        let elsewhere = loc.jump(&world, Dir6::North);
+        world.set_entity_location(items[2], elsewhere);
+        assert!(world.take(items[2]).is_err());
+    }
+
+    #[test]
+    fn test_bag_round_trip() {
+        let (mut world, player, loc) = new_world();
+        let chest = spawn(&mut world, "chest", loc);
+        let bag = give(&mut world, "bag");
+        let ration = give(&mut world, "ration");
+
+        // Both the chest underfoot and the carried bag are in reach.
+        assert_eq!(world.reachable_containers(player), vec![chest, bag]);
+
+        let slot = slot_of(&world, player, ration);
+        world.put_in(slot, bag).unwrap();
+        assert_eq!(world.contents(bag), vec![ration]);
+        assert!(world.contents(chest).is_empty());
+
+        // The ration in the carried bag can be taken back out.
+        assert_eq!(world.reachable_items(player), vec![ration]);
+        world.take_out(ration).unwrap();
+        assert!(world.contents(bag).is_empty());
+        assert!(world.reachable_items(player).is_empty());
+        assert!(Slot::iter().any(|&slot| world.entity_equipped(player, slot) == Some(ration)));
+    }
+
+    #[test]
+    fn test_cursed_equipment() {
+        let (mut world, player, loc) = new_world();
+
+        let helmet = spawn(&mut world, "helmet", loc);
+        world.ecs_mut().item[helmet].cursed = true;
+        world.take(helmet).unwrap();
+        assert!(!world.ecs().item[helmet].curse_known);
+
+        // The curse shows itself when the helmet is put on.
+        let slot = slot_of(&world, player, helmet);
+        world.equip(slot).unwrap();
+        assert!(world.ecs().item[helmet].curse_known);
+
+        // The helmet won't come off.
+        let slot = slot_of(&world, player, helmet);
+        assert!(slot.is_equipment_slot());
+        world.equip(slot).unwrap();
+        world.drop(slot).unwrap();
+        assert_eq!(world.entity_equipped(player, slot), Some(helmet));
+
+        let scroll = give(&mut world, "scroll of remove curse");
+        let scroll_slot = slot_of(&world, player, scroll);
+        world.use_item(scroll_slot).unwrap();
+        assert!(!world.ecs().item[helmet].cursed);
+
+        world.equip(slot).unwrap();
+        assert!(!slot_of(&world, player, helmet).is_equipment_slot());
+    }
+
+    #[test]
+    fn test_spells() {
+        let (mut world, player, _) = new_world();
+        assert_eq!(world.mana(player), 10);
+
+        let book = give(&mut world, "spellbook of healing");
+        let slot = slot_of(&world, player, book);
+
+        // Reading the book uses it up and puts the spell in the first spell slot.
+        world.use_item(slot).unwrap();
+        assert!(!world.entity_contains(player, book));
+        assert!(world.knows_spell(player, MagicEffect::Heal));
+        let spell = world.entity_equipped(player, Slot::Spell1).unwrap();
+        assert_eq!(world.item_type(spell), Some(ItemType::Spell(MagicEffect::Heal)));
+
+        // Spells aren't items to hand around.
+        assert!(world.drop(Slot::Spell1).is_err());
+        assert!(world.throw(Slot::Spell1, Dir6::North).is_err());
+        assert!(world.equip(Slot::Spell1).is_err());
+        assert_eq!(world.entity_equipped(player, Slot::Spell1), Some(spell));
+
+        // Healing isn't aimed.
+        assert!(world.cast(Slot::Spell1, Some(Dir6::North)).is_err());
+        assert_eq!(world.mana(player), 10);
+
+        let cost = MagicEffect::Heal.mana_cost();
+        world.cast(Slot::Spell1, None).unwrap();
+        assert_eq!(world.mana(player), 10 - cost);
+
+        while world.mana(player) >= cost {
+            world.cast(Slot::Spell1, None).unwrap();
+        }
+        assert!(world.cast(Slot::Spell1, None).is_err());
+
+        // Mana comes back with rest.
+        let mana = world.mana(player);
+        world.pass().unwrap();
+        assert!(world.mana(player) > mana);
+    }
+
+    #[test]
+    fn test_handless_doors() {
+        let (mut world, player, loc) = new_world();
+        let door = loc.jump(&world, Dir6::North);
+        world.set_terrain(door, Terrain::Door);
+        lose_hands(&mut world, player);
+
+        assert!(!world.can_enter(player, door));
+        assert!(world.open(Dir6::North).is_err());
+        assert!(world.step(Dir6::North).is_err());
+        assert_eq!(world.terrain(door), Terrain::Door);
+        assert_eq!(world.location(player), Some(loc));
+    }
+
+    #[test]
+    fn test_handless_take() {
+        let (mut world, player, loc) = new_world();
+        let sword = spawn(&mut world, "sword", loc);
+        lose_hands(&mut world, player);
+
+        assert!(world.take(sword).is_err());
+        assert_eq!(world.location(sword), Some(loc));
+        assert!(!world.entity_contains(player, sword));
+    }
+
+    #[test]
+    fn test_handless_equip() {
+        let (mut world, player, _) = new_world();
+        let sword = give(&mut world, "sword");
+        let slot = slot_of(&world, player, sword);
+        lose_hands(&mut world, player);
+
+        assert!(world.equip(slot).is_err());
+        assert_eq!(world.entity_equipped(player, slot), Some(sword));
+    }
+
+    #[test]
+    fn test_handless_zap() {
+        let (mut world, player, _) = new_world();
+        let wand = give(&mut world, "wand of fireball");
+        let slot = slot_of(&world, player, wand);
+        let charges = world.uses_left(wand);
+        lose_hands(&mut world, player);
+
+        assert!(world.zap_item(slot, Dir6::North).is_err());
+        assert_eq!(world.uses_left(wand), charges);
+    }
 }
@@ -1,5 +1,5 @@
 use calx_ecs::Entity;
-use item::ItemType;
+use item::{ItemType, MagicEffect};
 use location::Location;
 use location_set::LocationSet;
 use stats::Stats;
@@ -25,9 +25,22 @@ pub enum Icon {
     Sword,
     Helmet,
     Armor,
+    Bow,
+    Ring,
+    Potion,
+    Ration,
+    Corpse,
+    Key,
     Wand1,
     Wand2,
+    Wand3,
     Scroll1,
+    Bag,
+    Chest,
+    Book,
+    Trap,
+    Torch,
+    Arrow,
 }
 
 /// Entity name and appearance.
@@ -148,6 +161,21 @@ pub enum ShoutType {
     Silent,
 }
 
+impl ShoutType {
+    /// How far the shout can be heard.
+    pub fn loudness(self) -> u32 {
+        use self::ShoutType::*;
+        match self {
+            Shout => 8,
+            Hiss => 3,
+            Buzz => 5,
+            Roar => 10,
+            Gurgle => 4,
+            Silent => 0,
+        }
+    }
+}
+
 /// Used to determine who tries to fight whom.
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Alignment {
@@ -176,12 +204,105 @@ impl Health {
     pub fn new() -> Health { Default::default() }
 }
 
+/// How much food a mob has in its stomach.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Nutrition {
+    pub satiation: u32,
+}
+
+impl Nutrition {
+    /// Satiation of a full stomach.
+    pub const MAX: u32 = 2000;
+
+    pub fn hunger(&self) -> Hunger {
+        match self.satiation {
+            0 => Hunger::Starving,
+            1...50 => Hunger::Fainting,
+            51...200 => Hunger::Weak,
+            201...500 => Hunger::Hungry,
+            _ => Hunger::Satiated,
+        }
+    }
+}
+
+impl Default for Nutrition {
+    fn default() -> Nutrition {
+        Nutrition {
+            satiation: Nutrition::MAX,
+        }
+    }
+}
+
+/// Stages of getting hungry, from least to most severe.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum Hunger {
+    Satiated,
+    Hungry,
+    Weak,
+    Fainting,
+    Starving,
+}
+
+impl Hunger {
+    /// Attack and defense penalty from hunger.
+    pub fn penalty(self) -> i32 {
+        match self {
+            Hunger::Satiated | Hunger::Hungry => 0,
+            Hunger::Weak => 2,
+            Hunger::Fainting | Hunger::Starving => 4,
+        }
+    }
+}
+
+/// Experience gathered by a character that can gain levels.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Experience {
+    pub xp: u32,
+    pub level: u32,
+}
+
+impl Default for Experience {
+    fn default() -> Experience { Experience { xp: 0, level: 1 } }
+}
+
+/// Mana spent from the pool whose size is given by `Stats.mana`, regenerates over time.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Mana {
+    pub spent: i32,
+}
+
 /// Items can be picked up and carried and they do stuff.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Item {
     pub item_type: ItemType,
     /// How many uses a wand or similar has left.
     pub charges: u32,
+    /// Cursed items can't be unequipped.
+    #[serde(default)]
+    pub cursed: bool,
+    /// Whether the wielder has found out about the curse.
+    #[serde(default)]
+    pub curse_known: bool,
+    /// Number of identical items in a stack.
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+fn default_count() -> u32 { 1 }
+
+/// Entity that can hold items inside it.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Container {
+    /// How many separate items fit inside.
+    pub capacity: u32,
+}
+
+/// Magic set in the floor that goes off when something steps on it.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Trap {
+    pub effect: MagicEffect,
+    /// Hidden traps aren't shown to the player until found.
+    pub hidden: bool,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug, Serialize, Deserialize)]
@@ -199,6 +320,50 @@ pub enum Status {
     ///
     /// This gets jumped up every time after the creature acted.
     Delayed,
+    /// Takes poison damage over time.
+    Poisoned,
+    /// Can't act until the status runs out or the creature gets hurt.
+    Asleep,
+    /// Creature runs away from enemies.
+    Afraid,
+    /// Heals wounds over time.
+    Regenerating,
+    /// Can't be seen by others.
+    Invisible,
+}
+
+impl Status {
+    /// Message template for when the status takes effect.
+    pub fn onset_msg(self) -> Option<&'static str> {
+        use self::Status::*;
+        match self {
+            Confused => Some("[One] [is] confused."),
+            Slowed => Some("[One] slow[s] down."),
+            Hasted => Some("[One] speed[s] up."),
+            Poisoned => Some("[One] [is] poisoned."),
+            Asleep => Some("[One] fall[s] asleep."),
+            Afraid => Some("[One] [is] terrified."),
+            Regenerating => Some("[One] feel[s] vigorous."),
+            Invisible => Some("[One] vanish[es] from sight."),
+            Dead | Delayed => None,
+        }
+    }
+
+    /// Message template for when the status runs out.
+    pub fn expire_msg(self) -> Option<&'static str> {
+        use self::Status::*;
+        match self {
+            Confused => Some("[One] [is] no longer confused."),
+            Slowed => Some("[One] [is] no longer slowed."),
+            Hasted => Some("[One] slow[s] back down."),
+            Poisoned => Some("[One] [is] no longer poisoned."),
+            Asleep => Some("[One] wake[s] up."),
+            Afraid => Some("[One] regain[s] courage."),
+            Regenerating => Some("[One] stop[s] regenerating."),
+            Invisible => Some("[One] reappear[s]."),
+            Dead | Delayed => None,
+        }
+    }
 }
 
 pub type Statuses = BTreeMap<Status, u32>;
@@ -1,3 +1,6 @@
+use components::Status;
+use stats::Intrinsic;
+
 /// Game system effects on entities.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Effect {
@@ -7,16 +10,41 @@ pub enum Effect {
     Hit { amount: u32, damage: Damage },
     /// Cause erratic behavior for a time.
     Confuse,
+    /// Apply a status for a duration.
+    Status(Status, u32),
     /// Target mob learns current surroundings.
     ///
     /// Probably only does anything for player.
     MagicMap,
+    /// Lift curses from carried items.
+    RemoveCurse,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Damage {
     Physical,
     Fire,
     Electricity,
     Cold,
+    /// Poison works from the inside and isn't stopped by armor.
+    Poison,
+}
+
+impl Damage {
+    /// Return the immunity, resistance and vulnerability intrinsics for the damage type.
+    pub fn resistances(self) -> Option<(Intrinsic, Intrinsic, Intrinsic)> {
+        use self::Damage::*;
+        use stats::Intrinsic::*;
+        match self {
+            Physical => None,
+            Fire => Some((FireImmunity, FireResistance, FireVulnerability)),
+            Electricity => Some((
+                ElectricityImmunity,
+                ElectricityResistance,
+                ElectricityVulnerability,
+            )),
+            Cold => Some((ColdImmunity, ColdResistance, ColdVulnerability)),
+            Poison => None,
+        }
+    }
 }
@@ -1,4 +1,9 @@
 use calx_ecs::Entity;
+use components::Status;
+use effect::Damage;
+use item::MagicEffect;
+use location::Location;
+use volume::Volume;
 
 /// Immediate events emitted by game events.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -10,4 +15,97 @@ pub enum Event {
 
     /// Damage dealt to an entity. Use negative values to show healing.
     Damage { entity: Entity, amount: i32 },
+
+    /// Entity moved from one location to another.
+    Move {
+        entity: Entity,
+        from: Location,
+        to: Location,
+    },
+
+    /// Attack connected.
+    Hit {
+        attacker: Entity,
+        target: Entity,
+        damage: i32,
+    },
+
+    /// Attack missed.
+    Miss { attacker: Entity, target: Entity },
+
+    /// Projectile flew from one location to another.
+    Shot {
+        shooter: Entity,
+        from: Location,
+        to: Location,
+    },
+
+    /// Item was thrown from one location to another.
+    Throw {
+        thrower: Entity,
+        item: Entity,
+        from: Location,
+        to: Location,
+    },
+
+    /// Entity was killed by damage of the given type.
+    Death { entity: Entity, damage: Damage },
+
+    /// Entity picked up an item.
+    PickUp { entity: Entity, item: Entity },
+
+    /// Entity dropped an item.
+    Drop { entity: Entity, item: Entity },
+
+    /// Entity put an item in a container.
+    PutIn {
+        entity: Entity,
+        item: Entity,
+        container: Entity,
+    },
+
+    /// Entity took an item out of a container.
+    TakeOut {
+        entity: Entity,
+        item: Entity,
+        container: Entity,
+    },
+
+    /// Entity equipped an item.
+    Equip { entity: Entity, item: Entity },
+
+    /// Entity moved an equipped item back to inventory.
+    Unequip { entity: Entity, item: Entity },
+
+    /// Entity gained enough experience to reach a new level.
+    LevelUp { entity: Entity, level: u32 },
+
+    /// Entity came under the effect of a status.
+    StatusGained { entity: Entity, status: Status },
+
+    /// Status effect on entity ran out.
+    StatusExpired { entity: Entity, status: Status },
+
+    /// Magic effect was cast over an area.
+    Spell {
+        caster: Option<Entity>,
+        effect: MagicEffect,
+        volume: Volume,
+    },
+
+    /// Entity blew up when it died.
+    Explosion { entity: Entity, volume: Volume },
+
+    /// Entity opened a door at location.
+    DoorOpened { entity: Entity, loc: Location },
+
+    /// Entity closed a door at location.
+    DoorClosed { entity: Entity, loc: Location },
+
+    /// Entity went through a portal to another level.
+    Stairs {
+        entity: Entity,
+        from: Location,
+        to: Location,
+    },
 }
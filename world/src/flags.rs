@@ -1,5 +1,6 @@
 use calx_ecs::Entity;
 use location::Location;
+use std::collections::BTreeSet;
 
 #[derive(Serialize, Deserialize)]
 pub struct Flags {
@@ -9,6 +10,9 @@ pub struct Flags {
     /// Store the player entity here for fast access.
     pub player: Option<Entity>,
     pub depth: i32,
+    /// Spawn names of the item kinds the player has identified.
+    #[serde(default)]
+    pub identified: BTreeSet<String>,
 }
 
 impl Flags {
@@ -19,6 +23,7 @@ impl Flags {
             player_acted: false,
             player: None,
             depth: 0,
+            identified: BTreeSet::new(),
         }
     }
 }
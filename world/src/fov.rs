@@ -104,3 +104,87 @@ impl<'a> FovValue for SphereVolumeFov<'a> {
         Some(ret)
     }
 }
+
+#[derive(Clone)]
+/// Field for sound that spreads along walkable terrain.
+pub struct NoiseFov<'a> {
+    w: &'a World,
+    range: u32,
+    pub origin: Location,
+}
+
+impl<'a> NoiseFov<'a> {
+    pub fn new(w: &'a World, range: u32, origin: Location) -> NoiseFov<'a> {
+        NoiseFov { w, range, origin }
+    }
+}
+
+impl<'a> PartialEq for NoiseFov<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.w as *const World == other.w as *const World
+            && self.range == other.range
+            && self.origin == other.origin
+    }
+}
+
+impl<'a> Eq for NoiseFov<'a> {}
+
+impl<'a> FovValue for NoiseFov<'a> {
+    fn advance(&self, offset: CellVector) -> Option<Self> {
+        if offset.hex_dist() as u32 > self.range {
+            return None;
+        }
+
+        let mut ret = self.clone();
+        // Sound carries through all portals, including ones that aren't visible like stairwells.
+        if let Some(dest) = self.w.portal(self.origin + offset) {
+            ret.origin = dest - offset;
+        }
+
+        if self.w.terrain(ret.origin + offset).blocks_walk() {
+            return None;
+        }
+
+        Some(ret)
+    }
+}
+
+#[derive(Clone)]
+/// Field for looking up light sources, goes through walls but only follows visible portals.
+pub struct LightRangeFov<'a> {
+    w: &'a World,
+    range: u32,
+    pub origin: Location,
+}
+
+impl<'a> LightRangeFov<'a> {
+    pub fn new(w: &'a World, range: u32, origin: Location) -> LightRangeFov<'a> {
+        LightRangeFov { w, range, origin }
+    }
+}
+
+impl<'a> PartialEq for LightRangeFov<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.w as *const World == other.w as *const World
+            && self.range == other.range
+            && self.origin == other.origin
+    }
+}
+
+impl<'a> Eq for LightRangeFov<'a> {}
+
+impl<'a> FovValue for LightRangeFov<'a> {
+    fn advance(&self, offset: CellVector) -> Option<Self> {
+        if offset.hex_dist() as u32 > self.range {
+            return None;
+        }
+
+        let mut ret = self.clone();
+        // Light only shines through the portals sight goes through.
+        if let Some(dest) = self.w.visible_portal(self.origin + offset) {
+            ret.origin = dest - offset;
+        }
+
+        Some(ret)
+    }
+}
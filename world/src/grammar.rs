@@ -13,6 +13,7 @@ pub struct Noun {
     is_you: bool,
     name: String,
     pronoun: Pronoun,
+    count: u32,
 }
 
 impl Noun {
@@ -21,6 +22,24 @@ impl Noun {
             is_you: false,
             pronoun: Pronoun::It,
             name,
+            count: 1,
+        }
+    }
+
+    /// Set the number of things the noun stands for, more than one makes it plural.
+    pub fn count(mut self, count: u32) -> Noun {
+        self.count = count;
+        self
+    }
+
+    pub fn is_plural(&self) -> bool { !self.is_you && self.count > 1 }
+
+    /// Bare name with the count in front for plurals, "3 scrolls of lightning".
+    pub fn name(&self) -> String {
+        if self.count > 1 {
+            format!("{} {}", self.count, pluralize(&self.name))
+        } else {
+            self.name.clone()
         }
     }
 
@@ -40,17 +59,17 @@ impl Noun {
         if self.is_you {
             "you".to_string()
         } else if self.is_proper_noun() {
-            self.name.to_string()
+            self.name()
         } else {
-            format!("the {}", self.name)
+            format!("the {}", self.name())
         }
     }
 
     pub fn a_name(&self) -> String {
         if self.is_you {
             "you".to_string()
-        } else if self.is_proper_noun() {
-            self.name.to_string()
+        } else if self.is_proper_noun() || self.is_plural() {
+            self.name()
         } else {
             // TODO: Add look-up table of irregular words ('honor', 'unit') as they show up in game
             // text.
@@ -66,6 +85,8 @@ impl Noun {
     pub fn they(&self) -> String {
         if self.is_you {
             "you".to_string()
+        } else if self.is_plural() {
+            "they".to_string()
         } else {
             match self.pronoun {
                 Pronoun::He => "he".to_string(),
@@ -79,6 +100,8 @@ impl Noun {
     pub fn them(&self) -> String {
         if self.is_you {
             "you".to_string()
+        } else if self.is_plural() {
+            "them".to_string()
         } else {
             match self.pronoun {
                 Pronoun::He => "him".to_string(),
@@ -93,7 +116,9 @@ impl Noun {
         if self.is_you {
             "your".to_string()
         } else if self.is_proper_noun() {
-            format!("{}'s", self.name.to_string())
+            format!("{}'s", self.name())
+        } else if self.is_plural() {
+            format!("the {}'", self.name())
         } else {
             format!("the {}'s", self.name)
         }
@@ -102,6 +127,8 @@ impl Noun {
     pub fn reflexive(&self) -> String {
         if self.is_you {
             "yourself".to_string()
+        } else if self.is_plural() {
+            "themselves".to_string()
         } else {
             match self.pronoun {
                 Pronoun::He => "himself".to_string(),
@@ -158,6 +185,11 @@ impl SubjectTemplater {
             used_pronoun_they: false,
         }
     }
+
+    /// Whether verbs take the "you do" / "they do" form instead of "it does".
+    fn plural_verb(&self) -> bool {
+        self.subject.is_you || self.subject.is_plural() || self.used_pronoun_they
+    }
 }
 
 impl Templater for SubjectTemplater {
@@ -184,7 +216,7 @@ impl Templater for SubjectTemplater {
             // All of these are assummed to apply to subject.
             // hit/hits
             "s" => {
-                if self.subject.is_you || self.used_pronoun_they {
+                if self.plural_verb() {
                     "".to_string()
                 } else {
                     "s".to_string()
@@ -192,7 +224,7 @@ impl Templater for SubjectTemplater {
             }
             // slash/slashes
             "es" => {
-                if self.subject.is_you || self.used_pronoun_they {
+                if self.plural_verb() {
                     "".to_string()
                 } else {
                     "es".to_string()
@@ -200,21 +232,21 @@ impl Templater for SubjectTemplater {
             }
             // parry/parries
             "ies" => {
-                if self.subject.is_you || self.used_pronoun_they {
+                if self.plural_verb() {
                     "y".to_string()
                 } else {
                     "ies".to_string()
                 }
             }
             "is" | "are" => {
-                if self.subject.is_you || self.used_pronoun_they {
+                if self.plural_verb() {
                     "are".to_string()
                 } else {
                     "is".to_string()
                 }
             }
             "has" | "have" => {
-                if self.subject.is_you || self.used_pronoun_they {
+                if self.plural_verb() {
                     "have".to_string()
                 } else {
                     "has".to_string()
@@ -274,6 +306,32 @@ pub fn capitalize(word: &str) -> String {
     cap.chain(iter).collect()
 }
 
+/// Turn a singular noun phrase into a plural one.
+///
+/// Only the head noun is inflected, so "scroll of lightning" becomes "scrolls of lightning".
+pub fn pluralize(name: &str) -> String {
+    let head_len = [" of ", " labeled "]
+        .iter()
+        .filter_map(|sep| name.find(sep))
+        .min()
+        .unwrap_or(name.len());
+    let (head, tail) = name.split_at(head_len);
+
+    let inflected = if head.ends_with('s')
+        || head.ends_with('x')
+        || head.ends_with('z')
+        || head.ends_with("ch")
+        || head.ends_with("sh")
+    {
+        format!("{}es", head)
+    } else if head.ends_with('y') && !head[..head.len() - 1].ends_with(is_vowel) {
+        format!("{}ies", &head[..head.len() - 1])
+    } else {
+        format!("{}s", head)
+    };
+    format!("{}{}", inflected, tail)
+}
+
 pub fn is_vowel(c: char) -> bool {
     // If accented chars are used, they need to be added here...
     match c.to_lowercase().next().unwrap_or('\0') {
@@ -451,4 +509,46 @@ mod test {
             assert_eq!(t.format(template), Ok(message.to_string()));
         }
     }
+
+    #[test]
+    fn test_pluralize() {
+        use super::pluralize;
+        for &(singular, plural) in &[
+            ("sword", "swords"),
+            ("potion of healing", "potions of healing"),
+            ("scroll labeled XYZZY", "scrolls labeled XYZZY"),
+            ("murky potion", "murky potions"),
+            ("glass", "glasses"),
+            ("torch", "torches"),
+            ("ruby", "rubies"),
+            ("key", "keys"),
+        ] {
+            assert_eq!(&pluralize(singular), plural);
+        }
+    }
+
+    #[test]
+    fn test_plural_nouns() {
+        let potions = Noun::new("potion of healing".to_string()).count(3);
+        assert_eq!(&potions.a_name(), "3 potions of healing");
+        assert_eq!(&potions.the_name(), "the 3 potions of healing");
+
+        let single = Noun::new("potion of healing".to_string()).count(1);
+        assert_eq!(&single.a_name(), "a potion of healing");
+
+        let mut t = SubjectTemplater::new(potions.clone());
+        assert_eq!(
+            t.format("[One] shatter[s]. [They] [are] gone."),
+            Ok("The 3 potions of healing shatter. They are gone.".to_string())
+        );
+
+        let mut t = ObjectTemplater::new(
+            SubjectTemplater::new(make_noun("PLAYER")),
+            Noun::new("scroll of lightning".to_string()).count(2),
+        );
+        assert_eq!(
+            t.format("[One] pick[s] up [another]."),
+            Ok("You pick up the 2 scrolls of lightning.".to_string())
+        );
+    }
 }
@@ -1,3 +1,5 @@
+use components::Status;
+use effect::{Damage, Effect};
 use std::slice;
 
 /// Inventory slots.
@@ -42,6 +44,9 @@ pub enum Slot {
 impl Slot {
     pub fn is_equipment_slot(self) -> bool { (self as u32) <= (Slot::TrinketI as u32) }
 
+    /// Spell slots hold known spells, which can't be moved around like items.
+    pub fn is_spell_slot(self) -> bool { (self as u32) <= (Slot::Spell8 as u32) }
+
     pub fn accepts(self, equip_type: EquipType) -> bool {
         use Slot::*;
         match self {
@@ -90,11 +95,22 @@ pub enum ItemType {
     Boots,
     /// Passive effects when equipped
     Trinket,
-    Spell,
+    /// Known spell, cast from a spell slot with mana.
+    Spell(MagicEffect),
+    /// Teaches a spell when read.
+    Spellbook(MagicEffect),
     UntargetedUsable(MagicEffect),
     TargetedUsable(MagicEffect),
     /// Consumed instantly when stepped on.
     Instant(MagicEffect),
+    /// Can be eaten for this much nutrition.
+    Food(u32),
+    /// Unlocks a locked door, used up in the process.
+    Key,
+    /// Holds other items, comes with a `Container` component.
+    Container,
+    /// Shot from a ranged weapon, used up in the process.
+    Ammo,
 }
 
 #[derive(Copy, Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
@@ -103,6 +119,126 @@ pub enum MagicEffect {
     Confuse,
     Lightning,
     Fireball,
+    MagicMap,
+    Sleep,
+    Fear,
+    Regeneration,
+    Invisibility,
+    RemoveCurse,
+}
+
+impl MagicEffect {
+    /// Effect on the creature drinking or reading the magic, or getting splashed with it.
+    pub fn self_effect(self) -> Option<Effect> {
+        use self::MagicEffect::*;
+        const HEAL_AMOUNT: u32 = 10;
+
+        match self {
+            Heal => Some(Effect::Heal(HEAL_AMOUNT)),
+            MagicMap => Some(Effect::MagicMap),
+            RemoveCurse => Some(Effect::RemoveCurse),
+            Confuse => Some(Effect::Confuse),
+            Regeneration => Some(Effect::Status(Status::Regenerating, 240)),
+            Invisibility => Some(Effect::Status(Status::Invisible, 600)),
+            _ => None,
+        }
+    }
+
+    /// Effect on the creature stepping on an instant item or a trap with the magic.
+    pub fn step_effect(self) -> Option<Effect> {
+        use self::MagicEffect::*;
+        match self {
+            Lightning => Some(Effect::Hit {
+                amount: 8,
+                damage: Damage::Electricity,
+            }),
+            Fireball => Some(Effect::Hit {
+                amount: 6,
+                damage: Damage::Fire,
+            }),
+            Sleep => Some(Effect::Status(Status::Asleep, 120)),
+            Fear => Some(Effect::Status(Status::Afraid, 120)),
+            _ => self.self_effect(),
+        }
+    }
+
+    /// Whether the magic needs to be aimed in a direction when cast as a spell.
+    pub fn is_directed(self) -> bool {
+        use self::MagicEffect::*;
+        match self {
+            Fireball | Confuse | Sleep | Fear => true,
+            _ => false,
+        }
+    }
+
+    /// Mana needed to cast the magic as a spell.
+    pub fn mana_cost(self) -> i32 {
+        use self::MagicEffect::*;
+        match self {
+            Heal | Confuse | Sleep | Fear => 3,
+            Lightning | Fireball => 5,
+            RemoveCurse | Regeneration | Invisibility => 8,
+            MagicMap => 10,
+        }
+    }
+
+    /// Name of the spell that casts the magic.
+    pub fn spell_name(self) -> &'static str {
+        use self::MagicEffect::*;
+        match self {
+            Heal => "healing",
+            Confuse => "confusion",
+            Lightning => "lightning",
+            Fireball => "fireball",
+            MagicMap => "magic mapping",
+            Sleep => "sleep",
+            Fear => "fear",
+            Regeneration => "regeneration",
+            Invisibility => "invisibility",
+            RemoveCurse => "remove curse",
+        }
+    }
+}
+
+/// Shared look of item kinds that aren't recognized until identified.
+#[derive(Copy, Eq, PartialEq, Clone, Hash, Debug)]
+pub enum Appearance {
+    Wand,
+    Potion,
+    Scroll,
+}
+
+impl Appearance {
+    /// Labels that get shuffled between the item kinds with this appearance.
+    pub fn labels(self) -> &'static [&'static str] {
+        match self {
+            Appearance::Wand => &[
+                "oak", "bone", "iron", "glass", "crystal", "copper", "ebony", "silver",
+            ],
+            Appearance::Potion => &[
+                "murky", "bubbling", "fizzy", "violet", "golden", "smoky", "cloudy", "glowing",
+            ],
+            Appearance::Scroll => &[
+                "XYZZY",
+                "FOOBIE BLETCH",
+                "ZELGO MER",
+                "ELBIB YLOH",
+                "VERR YED HORRE",
+                "KIRJE",
+                "NR 9",
+                "PRATYAVAYAH",
+            ],
+        }
+    }
+
+    /// Name of an unidentified item with the label.
+    pub fn describe(self, label: &str) -> String {
+        match self {
+            Appearance::Wand => format!("{} wand", label),
+            Appearance::Potion => format!("{} potion", label),
+            Appearance::Scroll => format!("scroll labeled {}", label),
+        }
+    }
 }
 
 #[derive(Copy, Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
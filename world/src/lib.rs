@@ -118,6 +118,7 @@ mod components;
 pub use components::Icon;
 
 mod effect;
+pub use effect::Damage;
 
 mod event;
 pub use event::Event;
@@ -131,6 +132,8 @@ mod grammar;
 mod item;
 pub use item::{ItemType, Slot};
 
+mod light;
+
 mod location;
 pub use location::{Location, Portal, Sector, SECTOR_HEIGHT, SECTOR_WIDTH};
 
@@ -141,12 +144,24 @@ pub use mapsave::MapSave;
 
 mod map;
 
+mod migrate;
+pub use migrate::LoadError;
+
 mod mutate;
 pub use mutate::Mutate;
 
+mod overlay;
+
+mod pathing;
+
+mod progression;
+
 mod query;
 pub use query::Query;
 
+mod replay;
+pub use replay::{Recorder, RecordedCommand, Replay, ReplayError};
+
 mod spatial;
 mod spec;
 mod stats;
@@ -157,6 +172,9 @@ pub use terraform::{Terraform, TerrainQuery};
 pub mod terrain;
 pub use terrain::Terrain;
 
+#[cfg(test)]
+mod test_util;
+
 mod vaults;
 
 mod volume;
@@ -182,7 +200,8 @@ pub trait Distribution<T> {
 /// The combat formula.
 ///
 /// Given a deciban roll and the relevant stats, determine amount of damage dealt.
-/// Advantage is attacker skill - target defense.
+/// Advantage is attacker skill - target defense. Melee attacks add twice the target's armor to
+/// it, since armor makes the target easier to hit while it soaks up the damage.
 pub fn attack_damage(roll: f32, advantage: i32, weapon_power: i32) -> i32 {
     const MAX_DAMAGE_MULTIPLIER: f32 = 4.0;
 
@@ -190,6 +209,27 @@ pub fn attack_damage(roll: f32, advantage: i32, weapon_power: i32) -> i32 {
     (weapon_power as f32 * calx::clamp(0.0, MAX_DAMAGE_MULTIPLIER, (roll - 2.0) * 0.05)) as i32
 }
 
+/// The armor formula.
+///
+/// Given incoming damage, the target's current armor points and the type of the damage, return
+/// the pair of armor points lost and wounds taken. Armor stops physical damage fully, lets half of
+/// fire and cold through and does nothing against electricity or poison.
+pub fn armor_absorb(amount: i32, armor: i32, damage_type: effect::Damage) -> (i32, i32) {
+    use effect::Damage::*;
+
+    if amount <= 0 || armor <= 0 {
+        return (0, amount.max(0));
+    }
+
+    let absorbable = match damage_type {
+        Physical => amount,
+        Fire | Cold => amount / 2,
+        Electricity | Poison => 0,
+    };
+    let absorbed = absorbable.min(armor);
+    (absorbed, amount - absorbed)
+}
+
 /// Standard deciban roll, clamp into [-20, 20].
 pub fn roll(rng: &mut impl rand::Rng) -> f32 { calx::clamp(-20.0, 20.0, rng.gen::<Deciban>().0) }
 
@@ -206,6 +246,10 @@ impl<T: Default> Cache<T> {
     }
 }
 
+impl<T: Default> Default for Cache<T> {
+    fn default() -> Cache<T> { Cache::new() }
+}
+
 impl<T> ::std::ops::Deref for Cache<T> {
     type Target = T;
 
@@ -225,3 +269,39 @@ impl<'a, T: Default> serde::Deserialize<'a> for Cache<T> {
         Ok(Cache::new())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{armor_absorb, attack_damage};
+    use effect::Damage;
+
+    #[test]
+    fn test_attack_damage() {
+        // Low rolls miss.
+        assert_eq!(attack_damage(0.0, 0, 10), 0);
+        assert_eq!(attack_damage(10.0, 0, 10), 4);
+        // Advantage adds to the roll, so an armored target at melee takes harder hits.
+        let armor = 2;
+        assert_eq!(attack_damage(10.0, 2 * armor, 10), 6);
+        assert_eq!(attack_damage(10.0, -10, 10), 0);
+        // Damage is capped at a multiple of weapon power.
+        assert_eq!(attack_damage(100.0, 0, 10), 40);
+    }
+
+    #[test]
+    fn test_armor_absorb() {
+        // No armor, all damage goes to wounds.
+        assert_eq!(armor_absorb(5, 0, Damage::Physical), (0, 5));
+        // Armor absorbs physical damage completely while it lasts.
+        assert_eq!(armor_absorb(5, 10, Damage::Physical), (5, 0));
+        assert_eq!(armor_absorb(5, 3, Damage::Physical), (3, 2));
+        // Fire and cold get halfway through.
+        assert_eq!(armor_absorb(6, 10, Damage::Fire), (3, 3));
+        assert_eq!(armor_absorb(6, 2, Damage::Cold), (2, 4));
+        // Electricity and poison ignore armor.
+        assert_eq!(armor_absorb(6, 10, Damage::Electricity), (0, 6));
+        assert_eq!(armor_absorb(2, 10, Damage::Poison), (0, 2));
+        // Nothing happens with no damage.
+        assert_eq!(armor_absorb(0, 10, Damage::Physical), (0, 0));
+    }
+}
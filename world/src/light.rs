@@ -0,0 +1,67 @@
+//! Light cast by light sources in the world.
+
+use calx::{HexFov, HexGeom};
+use fov::{LightRangeFov, SightFov};
+use location::Location;
+use query::Query;
+use std::collections::HashMap;
+use terraform::TerrainQuery;
+use world::World;
+
+/// Brightness of the locations lit by light sources.
+#[derive(Clone, Debug, Default)]
+pub struct LightMap(HashMap<Location, f32>);
+
+/// How far from the player light sources are looked for.
+///
+/// The player's sight range plus the radius of a torch, so that any cell the player can see gets
+/// all the light that reaches it.
+const SOURCE_RANGE: u32 = 12;
+
+impl LightMap {
+    /// Compute the light from light-emitting entities and luminous terrain.
+    ///
+    /// Only light sources within `SOURCE_RANGE` of the player are looked at, walls don't stop the
+    /// search but portals are followed like with sight.
+    pub fn new(w: &World) -> LightMap {
+        let mut ret = LightMap::default();
+
+        let origin = match w.player().and_then(|p| w.location(p)) {
+            Some(loc) => loc,
+            None => return ret,
+        };
+
+        for (pos, a) in HexFov::new(LightRangeFov::new(w, SOURCE_RANGE, origin)) {
+            let loc = a.origin + pos;
+
+            let radius = w.terrain(loc).light_radius();
+            if radius > 0 {
+                ret.add_source(w, loc, radius);
+            }
+
+            // Carried light sources aren't on the floor, they're counted in the carrier's stats.
+            for e in w.entities_at(loc) {
+                let radius = w.stats(e).light;
+                if radius > 0 {
+                    ret.add_source(w, loc, radius);
+                }
+            }
+        }
+
+        ret
+    }
+
+    /// Return how brightly a location is lit, from 0.0 for unlit to 1.0 for fully lit.
+    pub fn get(&self, loc: Location) -> f32 { self.0.get(&loc).cloned().unwrap_or(0.0) }
+
+    /// Spread light from a source, fading out towards the edge of the radius.
+    ///
+    /// Light goes through the same visible portals as sight and stops at the first wall.
+    fn add_source(&mut self, w: &World, origin: Location, radius: u32) {
+        for (pos, a) in HexFov::new(SightFov::new(w, radius, origin)) {
+            let brightness = 1.0 - pos.hex_dist() as f32 / (radius + 1) as f32;
+            let light = self.0.entry(a.origin + pos).or_insert(0.0);
+            *light = light.max(brightness);
+        }
+    }
+}
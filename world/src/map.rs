@@ -135,6 +135,29 @@ impl Map {
                         cell.terrain = Door;
                     }
                 }
+                '=' => {
+                    // Locked door, only makes sense inside the vault since dug entrances always
+                    // get regular doors.
+                    cell.terrain = LockedDoor;
+                }
+                '$' => {
+                    cell.terrain = Ground;
+                    cell.treasure = 1;
+                }
+                '&' => {
+                    cell.terrain = Ground;
+                    // Treasure gets generated after the chest, so it goes inside it.
+                    cell.spawns.push(EntitySpawn::from_str("chest").unwrap());
+                    cell.treasure = 2;
+                }
+                '^' => {
+                    cell.terrain = Ground;
+                    cell.spawns.push(EntitySpawn::from_str("fire trap").unwrap());
+                }
+                '*' => {
+                    cell.terrain = Ground;
+                    cell.spawns.push(EntitySpawn::from_str("sleeping gas trap").unwrap());
+                }
 
                 'a' => {
                     cell.terrain = Ground;
@@ -211,6 +234,32 @@ impl Map {
         self.find_positions(|_, c| c.terrain == Terrain::Exit)
     }
 
+    pub fn locked_doors(&self) -> Vec<CellVector> {
+        self.find_positions(|_, c| c.terrain == Terrain::LockedDoor)
+    }
+
+    pub fn treasure_sites(&self) -> Vec<CellVector> { self.find_positions(|_, c| c.treasure > 0) }
+
+    /// Return the walkable positions connected to `start` without going through locked doors.
+    ///
+    /// The result is guaranteed to be in stable order.
+    pub fn unlocked_region(&self, start: CellVector) -> Vec<CellVector> {
+        let floors: HashSet<CellVector> = self
+            .contents
+            .iter()
+            .filter_map(|(&p, c)| {
+                if c.is_walkable() && c.terrain != Terrain::LockedDoor {
+                    Some(p)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let mut ret: Vec<CellVector> = flood_fill(floors, start).into_iter().collect();
+        ret.sort_by_key(|v| (v.x, v.y));
+        ret
+    }
+
     pub fn open_ground(&self) -> Vec<CellVector> {
         self.find_positions(|_, c| c.is_walkable() && !c.is_border())
     }
@@ -621,6 +670,8 @@ fn flood_fill(mut points: HashSet<CellVector>, seed: CellVector) -> HashSet<Cell
 pub struct MapCell {
     pub terrain: Terrain,
     pub spawns: Vec<EntitySpawn>,
+    /// Number of random items to add after `spawns` when the level is generated.
+    pub treasure: u32,
     pub can_dig: bool,
     pub vault_kind: Option<VaultKind>,
 }
@@ -630,6 +681,7 @@ impl Default for MapCell {
         MapCell {
             terrain: Terrain::Empty,
             spawns: Vec::new(),
+            treasure: 0,
             can_dig: true,
             vault_kind: None,
         }
@@ -0,0 +1,520 @@
+//! Upgrading save files from older game versions.
+//!
+//! Migrations work on the RON text of the save before it is deserialized, since an old save
+//! generally can't be read into the current `World` type at all. Each step upgrades the save by
+//! one version, and steps are chained until the save reaches `SAVE_VERSION`. Steps can also
+//! repair the world after it has been deserialized, for changes that are awkward to make in the
+//! save text.
+
+use components::{Experience, Mana, Nutrition};
+use mutate::Mutate;
+use overlay::Overlay;
+use query::Query;
+use spec;
+use world::{Ecs, World};
+use ron;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+/// Current save file layout version.
+///
+/// Bump this and add a step to `MIGRATIONS` whenever a change makes old saves unreadable.
+pub const SAVE_VERSION: u32 = 7;
+
+/// Saves from before numeric save versions stored the game version string instead.
+static LEGACY_VERSIONS: &[(&str, u32)] = &[("0.1.0", 1)];
+
+struct Migration {
+    /// Version the step upgrades from, the result will have version `from + 1`.
+    from: u32,
+    upgrade: fn(&str) -> Result<String, String>,
+    /// Change to make to the loaded world after the save has been upgraded.
+    repair: Option<fn(&mut World)>,
+}
+
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        upgrade: add_terrain_overlay,
+        repair: None,
+    },
+    Migration {
+        from: 2,
+        upgrade: add_ecs_components,
+        repair: Some(give_player_nutrition),
+    },
+    Migration {
+        from: 3,
+        upgrade: add_ecs_components,
+        repair: None,
+    },
+    Migration {
+        from: 4,
+        upgrade: add_ecs_components,
+        repair: Some(give_player_experience),
+    },
+    Migration {
+        from: 5,
+        upgrade: add_ecs_components,
+        repair: Some(give_player_mana),
+    },
+    Migration {
+        from: 6,
+        upgrade: add_ecs_components,
+        repair: None,
+    },
+];
+
+/// Version 2 added the terrain overlay to `World`.
+fn add_terrain_overlay(doc: &str) -> Result<String, String> {
+    let overlay = ron::ser::to_string(&Overlay::new()).map_err(|e| e.to_string())?;
+    set_field(doc, "overlay", &overlay)
+}
+
+/// Versions 3 to 7 added the nutrition, container, experience, mana and trap components to
+/// `Ecs`.
+///
+/// New component stores start out empty, so fill in any missing ones from an empty `Ecs`.
+fn add_ecs_components(doc: &str) -> Result<String, String> {
+    let template = ron::ser::to_string(&Ecs::new()).map_err(|e| e.to_string())?;
+    let ecs = field(doc, "ecs")?.ok_or_else(|| "No ecs field".to_string())?;
+    let ecs = merge_missing(ecs, &template)?;
+    set_field(doc, "ecs", &ecs)
+}
+
+/// Version 3 gave the player a `Nutrition` component.
+fn give_player_nutrition(w: &mut World) {
+    if let Some(player) = w.player() {
+        w.ecs_mut().nutrition.insert(player, Nutrition::default());
+    }
+}
+
+/// Version 5 gave the player an `Experience` component.
+fn give_player_experience(w: &mut World) {
+    if let Some(player) = w.player() {
+        w.ecs_mut().experience.insert(player, Experience::default());
+    }
+}
+
+/// Version 6 gave the player a `Mana` component and a mana pool to cast spells with.
+fn give_player_mana(w: &mut World) {
+    if let Some(player) = w.player() {
+        w.ecs_mut().mana.insert(player, Mana::default());
+        w.ecs_mut().stats[player].base.mana = spec::PLAYER_MANA;
+        w.rebuild_stats(player);
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// Failed to read the save.
+    Io(io::Error),
+    /// Save does not have a recognizable version.
+    UnknownVersion(String),
+    /// Save is from a newer game than this one.
+    TooNew(u32),
+    /// There is no upgrade step from the save's version.
+    NoMigration(u32),
+    /// Save text could not be processed by the migration steps.
+    Malformed(String),
+    /// Upgraded save could not be deserialized.
+    Parse(ron::de::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::LoadError::*;
+        match *self {
+            Io(ref e) => write!(f, "Could not read save: {}", e),
+            UnknownVersion(ref v) => write!(f, "Unknown save version {}", v),
+            TooNew(v) => write!(
+                f,
+                "Save version {} is newer than current version {}",
+                v, SAVE_VERSION
+            ),
+            NoMigration(v) => write!(f, "Can't upgrade save from version {}", v),
+            Malformed(ref e) => write!(f, "Malformed save: {}", e),
+            Parse(ref e) => write!(f, "Could not parse save: {}", e),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> LoadError { LoadError::Io(e) }
+}
+
+impl From<ron::de::Error> for LoadError {
+    fn from(e: ron::de::Error) -> LoadError { LoadError::Parse(e) }
+}
+
+/// Load a world from a save document of any supported version.
+pub fn load(doc: String) -> Result<World, LoadError> {
+    let (doc, repairs) = upgrade(doc)?;
+    let mut world: World = ron::de::from_str(&doc)?;
+    for repair in repairs {
+        repair(&mut world);
+    }
+    Ok(world)
+}
+
+/// Upgrade a save document to the current save version.
+///
+/// Returns the upgraded document and the repairs to apply to the world once it is loaded.
+fn upgrade(mut doc: String) -> Result<(String, Vec<fn(&mut World)>), LoadError> {
+    let mut repairs = Vec::new();
+    loop {
+        let version = save_version(&doc)?;
+
+        if version == SAVE_VERSION {
+            return Ok((doc, repairs));
+        }
+        if version > SAVE_VERSION {
+            return Err(LoadError::TooNew(version));
+        }
+
+        let step = MIGRATIONS
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or(LoadError::NoMigration(version))?;
+
+        info!("Upgrading save from version {}", version);
+        doc = (step.upgrade)(&doc).map_err(LoadError::Malformed)?;
+        repairs.extend(step.repair);
+        doc = set_field(&doc, "version", &(version + 1).to_string())
+            .map_err(LoadError::Malformed)?;
+    }
+}
+
+/// Read the version of a save document.
+fn save_version(doc: &str) -> Result<u32, LoadError> {
+    let version = field(doc, "version")
+        .map_err(LoadError::Malformed)?
+        .ok_or_else(|| LoadError::UnknownVersion("N/A".to_string()))?;
+
+    if version.starts_with('"') {
+        let version = version.trim_matches('"');
+        LEGACY_VERSIONS
+            .iter()
+            .find(|&&(v, _)| v == version)
+            .map(|&(_, n)| n)
+            .ok_or_else(|| LoadError::UnknownVersion(version.to_string()))
+    } else {
+        version
+            .parse()
+            .map_err(|_| LoadError::UnknownVersion(version.to_string()))
+    }
+}
+
+// Minimal RON struct parsing for editing save text. Values are only parsed far enough to find
+// where they end, only structs with named fields are parsed into their components.
+
+/// Byte positions for the parts of a RON struct.
+struct StructSpan {
+    /// Position of the closing parenthesis.
+    end: usize,
+    fields: Vec<(String, Range<usize>)>,
+}
+
+impl StructSpan {
+    fn get(&self, name: &str) -> Option<Range<usize>> {
+        self.fields
+            .iter()
+            .find(|&&(ref n, _)| n == name)
+            .map(|&(_, ref r)| r.clone())
+    }
+
+    /// Return text that adds a new field at the end of the struct.
+    fn new_field(&self, doc: &str, name: &str, value: &str) -> String {
+        let needs_comma = match doc[..self.end].trim_right().chars().last() {
+            Some(',') | Some('(') => false,
+            _ => true,
+        };
+        format!("{}{}: {},", if needs_comma { "," } else { "" }, name, value)
+    }
+}
+
+fn skip_whitespace(s: &[u8], mut pos: usize) -> usize {
+    while pos < s.len() && (s[pos] as char).is_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+fn identifier_end(s: &[u8], mut pos: usize) -> usize {
+    while pos < s.len() && (s[pos].is_ascii_alphanumeric() || s[pos] == b'_') {
+        pos += 1;
+    }
+    pos
+}
+
+/// Find the end of a string or char literal starting at `pos`.
+fn quoted_end(s: &[u8], pos: usize) -> Result<usize, String> {
+    let quote = s[pos];
+    let mut i = pos + 1;
+    while i < s.len() {
+        if s[i] == b'\\' {
+            i += 2;
+        } else if s[i] == quote {
+            return Ok(i + 1);
+        } else {
+            i += 1;
+        }
+    }
+    Err(format!("Unterminated literal at {}", pos))
+}
+
+/// Find the end of the value starting at `pos`.
+fn value_end(s: &[u8], pos: usize) -> Result<usize, String> {
+    let mut depth = 0;
+    let mut i = pos;
+    while i < s.len() {
+        match s[i] {
+            b'"' | b'\'' => {
+                i = quoted_end(s, i)?;
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+            b',' if depth == 0 => break,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if depth != 0 {
+        return Err(format!("Unbalanced value at {}", pos));
+    }
+
+    while i > pos && (s[i - 1] as char).is_whitespace() {
+        i -= 1;
+    }
+    Ok(i)
+}
+
+/// Parse a document consisting of a single RON struct with named fields.
+fn parse_struct(doc: &str) -> Result<StructSpan, String> {
+    let s = doc.as_bytes();
+    // Skip struct name if there is one.
+    let mut pos = skip_whitespace(s, identifier_end(s, skip_whitespace(s, 0)));
+    if pos >= s.len() || s[pos] != b'(' {
+        return Err("Expected struct".to_string());
+    }
+    pos += 1;
+
+    let mut fields = Vec::new();
+    loop {
+        pos = skip_whitespace(s, pos);
+        if pos >= s.len() {
+            return Err("Unterminated struct".to_string());
+        }
+        if s[pos] == b')' {
+            break;
+        }
+
+        let name_end = identifier_end(s, pos);
+        if name_end == pos {
+            return Err(format!("Expected field name at {}", pos));
+        }
+        let name = doc[pos..name_end].to_string();
+
+        pos = skip_whitespace(s, name_end);
+        if pos >= s.len() || s[pos] != b':' {
+            return Err(format!("Expected ':' at {}", pos));
+        }
+
+        let start = skip_whitespace(s, pos + 1);
+        let end = value_end(s, start)?;
+        fields.push((name, start..end));
+
+        pos = skip_whitespace(s, end);
+        if pos < s.len() && s[pos] == b',' {
+            pos += 1;
+        }
+    }
+
+    Ok(StructSpan { end: pos, fields })
+}
+
+/// Return the value text of a struct field.
+fn field<'a>(doc: &'a str, name: &str) -> Result<Option<&'a str>, String> {
+    Ok(parse_struct(doc)?.get(name).map(|r| &doc[r]))
+}
+
+/// Set the value text of a struct field, adding the field if it is not present.
+fn set_field(doc: &str, name: &str, value: &str) -> Result<String, String> {
+    let span = parse_struct(doc)?;
+    let mut ret = doc.to_string();
+    if let Some(r) = span.get(name) {
+        ret.replace_range(r, value);
+    } else {
+        ret.insert_str(span.end, &span.new_field(doc, name, value));
+    }
+    Ok(ret)
+}
+
+/// Add fields from the template struct that are missing from the document struct.
+///
+/// Fields that are structs in both the document and the template are merged recursively, so
+/// missing fields are found however deep the ECS nests its component stores.
+fn merge_missing(doc: &str, template: &str) -> Result<String, String> {
+    let span = parse_struct(template)?;
+    let mut ret = doc.to_string();
+    for &(ref name, ref range) in &span.fields {
+        let template_value = &template[range.clone()];
+        let merged = match field(&ret, name)? {
+            None => template_value.to_string(),
+            Some(value) => match merge_missing(value, template_value) {
+                Ok(merged) => merged,
+                // Not a struct, nothing to merge.
+                Err(_) => continue,
+            },
+        };
+        ret = set_field(&ret, name, &merged)?;
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_struct_editing() {
+        let doc = "(a: 1, b: (x: \"),\", y: [1, 2]), c: Some((1, 2)))";
+        assert_eq!(field(doc, "a"), Ok(Some("1")));
+        assert_eq!(field(doc, "b"), Ok(Some("(x: \"),\", y: [1, 2])")));
+        assert_eq!(field(doc, "c"), Ok(Some("Some((1, 2))")));
+        assert_eq!(field(doc, "d"), Ok(None));
+
+        assert_eq!(
+            set_field("(a: 1)", "a", "2"),
+            Ok("(a: 2)".to_string())
+        );
+        assert_eq!(
+            set_field("(a: 1)", "b", "2"),
+            Ok("(a: 1,b: 2,)".to_string())
+        );
+        assert_eq!(
+            set_field("(\n    a: 1,\n)", "b", "2"),
+            Ok("(\n    a: 1,\nb: 2,)".to_string())
+        );
+        assert!(set_field("[1, 2]", "a", "1").is_err());
+    }
+
+    #[test]
+    fn test_merge_missing() {
+        assert_eq!(
+            merge_missing("(a: 1, c: 3)", "(a: 0, b: [], c: 0)"),
+            Ok("(a: 1, c: 3,b: [],)".to_string())
+        );
+        assert_eq!(merge_missing("(a: 1)", "(a: 0)"), Ok("(a: 1)".to_string()));
+        assert_eq!(
+            merge_missing("(a: (x: 1), b: [1])", "(a: (x: 0, y: 0), b: [])"),
+            Ok("(a: (x: 1,y: 0,), b: [1])".to_string())
+        );
+    }
+
+    #[test]
+    fn test_save_version() {
+        assert_eq!(save_version("(version: \"0.1.0\", x: 1)").unwrap(), 1);
+        assert_eq!(save_version("(version: 2)").unwrap(), 2);
+        assert!(save_version("(version: \"0.0.1\")").is_err());
+        assert!(save_version("(x: 1)").is_err());
+    }
+
+    #[test]
+    fn test_upgrade_repairs() {
+        let mut world = World::new(FIXTURE_SEED);
+        let player = world.player().unwrap();
+        // Make the player look like they came from an old save.
+        world.ecs_mut().nutrition.remove(player);
+        world.ecs_mut().experience.remove(player);
+        world.ecs_mut().mana.remove(player);
+        world.ecs_mut().stats[player].base.mana = 0;
+        world.rebuild_stats(player);
+
+        let mut save = Vec::new();
+        world.save(&mut save).unwrap();
+        let doc = set_field(&String::from_utf8(save).unwrap(), "version", "2").unwrap();
+
+        let world = load(doc).unwrap();
+        assert!(world.ecs().nutrition.contains(player));
+        assert!(world.ecs().experience.contains(player));
+        assert!(world.ecs().mana.contains(player));
+        assert_eq!(world.mana(player), spec::PLAYER_MANA);
+    }
+
+    #[test]
+    fn test_golden_saves() {
+        use std::fs::{self, File};
+        use std::io::Read;
+        use std::path::Path;
+
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("saves");
+
+        for version in 1..=SAVE_VERSION {
+            assert!(
+                dir.join(format!("v{}.ron", version)).exists(),
+                "No golden save for version {}",
+                version
+            );
+        }
+
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(true, |e| e != "ron") {
+                continue;
+            }
+
+            let mut doc = String::new();
+            File::open(&path)
+                .unwrap()
+                .read_to_string(&mut doc)
+                .unwrap();
+            let world = World::load(&mut doc.as_bytes());
+            assert!(
+                world.is_ok(),
+                "Failed to load {:?}: {}",
+                path,
+                world.err().unwrap()
+            );
+
+            // Upgraded saves end up with everything a new player has.
+            let world = world.unwrap();
+            let player = world.player().unwrap();
+            assert!(world.ecs().nutrition.contains(player), "{:?}", path);
+            assert!(world.ecs().experience.contains(player), "{:?}", path);
+            assert!(world.ecs().mana.contains(player), "{:?}", path);
+            assert_eq!(world.mana(player), spec::PLAYER_MANA, "{:?}", path);
+        }
+    }
+
+    /// Snapshot the current save version into `saves/`.
+    ///
+    /// Run with `cargo test -- --ignored write_golden_save` after bumping `SAVE_VERSION` and
+    /// commit the new file along with the bump.
+    #[test]
+    #[ignore]
+    fn write_golden_save() {
+        use std::fs::File;
+        use std::path::Path;
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("saves")
+            .join(format!("v{}.ron", SAVE_VERSION));
+        assert!(!path.exists(), "Golden save {:?} already exists", path);
+        let mut file = File::create(&path).unwrap();
+        World::new(FIXTURE_SEED).save(&mut file).unwrap();
+    }
+
+    const FIXTURE_SEED: u32 = 1;
+}
@@ -1,20 +1,31 @@
 use calx::{Dir6, RngExt};
 use calx_ecs::Entity;
 use command::CommandResult;
-use components::{Brain, BrainState, MapMemory, Status};
+use components::{
+    Brain, BrainState, Desc, Experience, Hunger, Icon, Item, Mana, MapMemory, Nutrition, Status,
+};
 use effect::{Damage, Effect};
 use event::Event;
-use item::{ItemType, MagicEffect, Slot};
+use item::{EquipType, ItemType, MagicEffect, Slot};
 use location::Location;
 use mapsave;
+use progression;
 use query::Query;
 use rand::{seq, Rng};
 use spec;
+use stats::Intrinsic;
 use terraform::Terraform;
+use terrain::Terrain;
 use volume::Volume;
 use world::{Ecs, Loadout};
 use Distribution;
-use {attack_damage, roll};
+use {armor_absorb, attack_damage, roll};
+
+/// Loudness of the noise from opening or closing a door.
+const DOOR_LOUDNESS: u32 = 3;
+
+/// Loudness of the noise from an attack.
+const COMBAT_LOUDNESS: u32 = 4;
 
 /// World-mutating methods that are not exposed outside the crate.
 pub trait Mutate: Query + Terraform + Sized {
@@ -37,6 +48,9 @@ pub trait Mutate: Query + Terraform + Sized {
 
     fn equip_item(&mut self, e: Entity, parent: Entity, slot: Slot);
 
+    /// Put an entity inside a container.
+    fn stow_item(&mut self, e: Entity, container: Entity);
+
     fn set_player(&mut self, player: Option<Entity>);
 
     /// Mark an entity as dead, but don't remove it from the system yet.
@@ -56,6 +70,157 @@ pub trait Mutate: Query + Terraform + Sized {
     /// Push an event to the event queue for this tick.
     fn push_event(&mut self, event: Event);
 
+    /// Add an exploding entity to the explosion queue.
+    ///
+    /// Returns true if the queue was idle. The caller must then process the queue with
+    /// `next_explosion` until it runs out.
+    fn queue_explosion(&mut self, e: Entity, loc: Location) -> bool;
+
+    /// Take the next explosion from the queue.
+    ///
+    /// Returning `None` marks the queue idle again.
+    fn next_explosion(&mut self) -> Option<(Entity, Location)>;
+
+    /// Push a gameplay event and the message text describing it.
+    fn emit(&mut self, event: Event) {
+        self.push_event(event.clone());
+        self.describe(&event);
+    }
+
+    /// Generate the text message for a gameplay event.
+    fn describe(&mut self, event: &Event) {
+        match *event {
+            Event::Hit {
+                attacker,
+                target,
+                damage,
+            } => {
+                msg!(self, "[One] hit[s] [another] for {}.", damage)
+                    .subject(attacker)
+                    .object(target)
+                    .send();
+            }
+            Event::Miss { attacker, target } => {
+                msg!(self, "[One] miss[es] [another].")
+                    .subject(attacker)
+                    .object(target)
+                    .send();
+            }
+            Event::Death { entity, damage } => {
+                if self.location(entity).map_or(false, |loc| self.player_sees(loc)) {
+                    msg!(
+                        self,
+                        "[One] {}.",
+                        match damage {
+                            Damage::Physical => "die[s]",
+                            Damage::Fire => "burn[s] to ash",
+                            Damage::Electricity => "[is] electrocuted",
+                            Damage::Cold => "shatter[s] to frozen pieces",
+                            Damage::Poison => "succumb[s] to poison",
+                        }
+                    ).subject(entity)
+                        .send();
+                }
+            }
+            Event::PickUp { entity, item } => {
+                if self.is_player(entity) {
+                    msg!(self, "[One] pick[s] up [another].")
+                        .subject(entity)
+                        .object(item)
+                        .send();
+                }
+            }
+            Event::Drop { entity, item } => {
+                if self.is_player(entity) {
+                    msg!(self, "[One] drop[s] [another].")
+                        .subject(entity)
+                        .object(item)
+                        .send();
+                }
+            }
+            Event::Throw { thrower, item, .. } => {
+                if self.is_player(thrower) {
+                    msg!(self, "[One] throw[s] [another].")
+                        .subject(thrower)
+                        .object(item)
+                        .send();
+                }
+            }
+            Event::PutIn {
+                entity,
+                item,
+                container,
+            } => {
+                if self.is_player(entity) {
+                    let container = self.noun(container).the_name();
+                    msg!(self, "[One] put[s] [another] in {}.", container)
+                        .subject(entity)
+                        .object(item)
+                        .send();
+                }
+            }
+            Event::TakeOut {
+                entity,
+                item,
+                container,
+            } => {
+                if self.is_player(entity) {
+                    let container = self.noun(container).the_name();
+                    msg!(self, "[One] take[s] [another] out of {}.", container)
+                        .subject(entity)
+                        .object(item)
+                        .send();
+                }
+            }
+            Event::Equip { entity, item } => {
+                if self.is_player(entity) {
+                    msg!(self, "[One] equip[s] [another].")
+                        .subject(entity)
+                        .object(item)
+                        .send();
+                }
+            }
+            Event::Unequip { entity, item } => {
+                if self.is_player(entity) {
+                    msg!(self, "[One] remove[s] [another].")
+                        .subject(entity)
+                        .object(item)
+                        .send();
+                }
+            }
+            Event::Explosion { entity, ref volume } => {
+                if volume.0.iter().any(|&loc| self.player_sees(loc)) {
+                    msg!(self, "[One] explode[s]!").subject(entity).send();
+                }
+            }
+            Event::LevelUp { entity, level } => {
+                if self.is_player(entity) {
+                    msg!(self, "[One] reach[es] experience level {}.", level)
+                        .subject(entity)
+                        .send();
+                }
+            }
+            Event::StatusGained { entity, status } => {
+                if self.location(entity).map_or(false, |loc| self.player_sees(loc)) {
+                    if let Some(text) = status.onset_msg() {
+                        msg!(self, text).subject(entity).send();
+                    }
+                }
+            }
+            Event::StatusExpired { entity, status } => {
+                if self.location(entity).map_or(false, |loc| self.player_sees(loc)) {
+                    if let Some(text) = status.expire_msg() {
+                        msg!(self, text).subject(entity).send();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Remember that the player knows what items with the spawn name are.
+    fn mark_identified(&mut self, spawn_name: &str);
+
     /// Access the persistent random number generator.
     fn rng(&mut self) -> &mut ::Rng;
 
@@ -81,6 +246,10 @@ pub trait Mutate: Query + Terraform + Sized {
         const WAKEUP_DISTANCE: i32 = 5;
 
         use components::BrainState::*;
+        if self.has_status(npc, Status::Asleep) {
+            return;
+        }
+
         let brain_state = self.brain_state(npc).expect("Running AI for non-mob");
         match brain_state {
             Asleep => {
@@ -91,7 +260,7 @@ pub trait Mutate: Query + Terraform + Sized {
                     self.player(),
                     self.player().map(|p| self.location(p)).unwrap_or(None),
                 ) {
-                    if self.player_sees(loc) {
+                    if self.player_sees(loc) && !self.has_status(player, Status::Invisible) {
                         // Okay, tricky spot. Player might be seeing mob across a portal, in
                         // which case we can't do naive distance check.
                         // This could have a helper method that finds chart distance to self in
@@ -100,7 +269,12 @@ pub trait Mutate: Query + Terraform + Sized {
                         // For now, let's just go with mobs past portals not waking up to
                         // player.
 
-                        if loc.metric_distance(player_loc) <= WAKEUP_DISTANCE {
+                        // A player lurking in the dark is harder to spot.
+                        let light = self.light_level(player_loc);
+                        let wakeup_distance =
+                            ((WAKEUP_DISTANCE as f32 * light).ceil() as i32).max(1);
+
+                        if loc.metric_distance(player_loc) <= wakeup_distance {
                             self.designate_enemy(npc, player);
                         }
                     }
@@ -110,8 +284,30 @@ pub trait Mutate: Query + Terraform + Sized {
                 if let (Some(my_loc), Some(target_loc)) =
                     (self.location(npc), self.location(target))
                 {
-                    if my_loc.metric_distance(target_loc) == 1 {
-                        let _ = self.entity_melee(npc, my_loc.dir6_towards(target_loc).unwrap());
+                    let range = self.stats(npc).ranged_range as usize;
+                    let fire_dir = my_loc
+                        .dir6_towards(target_loc)
+                        .filter(|&dir| self.find_target(npc, dir, range) == Some(target));
+
+                    let melee_dir = my_loc
+                        .dir6_towards(target_loc)
+                        .filter(|_| my_loc.metric_distance(target_loc) == 1);
+
+                    if self.has_status(npc, Status::Afraid) {
+                        if let Some(dir) = self.flee_dir(npc, target_loc) {
+                            let _ = self.entity_step(npc, dir);
+                        } else if let Some(dir) = melee_dir {
+                            // Cornered, fight back.
+                            let _ = self.entity_melee(npc, dir);
+                        }
+                    } else if let Some(dir) = melee_dir {
+                        let _ = self.entity_melee(npc, dir);
+                    } else if self.has_status(target, Status::Invisible) {
+                        // Can't see where the target went, blunder around.
+                        let dir = self.rng().gen();
+                        let _ = self.entity_step(npc, dir);
+                    } else if let Some(dir) = fire_dir {
+                        let _ = self.entity_fire(npc, dir);
                     } else if let Some(move_dir) = self.pathing_dir_towards(npc, target_loc) {
                         let _ = self.entity_step(npc, move_dir);
                     }
@@ -125,8 +321,22 @@ pub trait Mutate: Query + Terraform + Sized {
     ///
     /// Applies delay.
     fn end_turn(&mut self, e: Entity) {
+        const STARVE_CHANCE_ONE_IN: u32 = 10;
+        const FAINT_CHANCE_ONE_IN: u32 = 20;
+        const FAINT_DURATION: u32 = 30;
+
         let delay = self.action_delay(e);
         self.gain_status(e, Status::Delayed, delay);
+
+        if !self.consume_nutrition(e) && self.rng().one_chance_in(STARVE_CHANCE_ONE_IN) {
+            self.damage(e, 1, Damage::Physical, None);
+        }
+
+        if self.hunger(e) >= Some(Hunger::Fainting) && self.rng().one_chance_in(FAINT_CHANCE_ONE_IN)
+        {
+            msg!(self, "[One] faint[s] from hunger.").subject(e).send();
+            self.gain_status(e, Status::Delayed, delay + FAINT_DURATION);
+        }
     }
 
     fn notify_attacked_by(&mut self, victim: Entity, attacker: Entity) {
@@ -138,20 +348,48 @@ pub trait Mutate: Query + Terraform + Sized {
     fn designate_enemy(&mut self, e: Entity, target: Entity) {
         // TODO: Probably want this logic to be more complex eventually.
         if self.is_npc(e) {
-            if self.brain_state(e) == Some(BrainState::Asleep) {
-                self.shout(e);
-            }
+            let was_asleep = self.brain_state(e) == Some(BrainState::Asleep);
+            // Set the state before shouting so that the shout waking up other mobs won't loop
+            // back to this one.
             if let Some(brain) = self.ecs_mut().brain.get_mut(e) {
                 brain.state = BrainState::Hunting(target);
             }
+            if was_asleep {
+                self.shout(e);
+            }
+        }
+    }
+
+    /// Make a noise that wakes up sleeping mobs that can hear it.
+    ///
+    /// Mobs that are hostile to `source` will start hunting it.
+    fn make_noise(&mut self, origin: Location, loudness: u32, source: Entity) {
+        if loudness == 0 {
+            return;
+        }
+
+        let volume = self.noise_volume(origin, loudness);
+        for &loc in &volume.0 {
+            for e in self.entities_at(loc) {
+                if self.brain_state(e) == Some(BrainState::Asleep) && self.is_hostile_to(e, source)
+                {
+                    self.designate_enemy(e, source);
+                }
+            }
         }
     }
 
     /// Make a mob shout according to its type.
     fn shout(&mut self, e: Entity) {
-        // TODO: Create noise, wake up other nearby monsters.
         use components::ShoutType;
         if let Some(shout) = self.ecs().brain.get(e).map(|b| b.shout) {
+            // Shouting alerts others to whatever the mob is hunting.
+            if let (Some(loc), Some(BrainState::Hunting(target))) =
+                (self.location(e), self.brain_state(e))
+            {
+                self.make_noise(loc, shout.loudness(), target);
+            }
+
             match shout {
                 ShoutType::Shout => {
                     msg!(self, "[One] shout[s] angrily.").subject(e).send();
@@ -215,9 +453,42 @@ pub trait Mutate: Query + Terraform + Sized {
         }
     }
 
+    fn entity_fire(&mut self, e: Entity, dir: Dir6) -> Result<(), ()> {
+        if self.confused_move(e) {
+            Ok(())
+        } else {
+            self.really_fire(e, dir)
+        }
+    }
+
+    fn entity_eat(&mut self, e: Entity, item: Entity) -> Result<(), ()> {
+        let value = match self.item_type(item) {
+            Some(ItemType::Food(value)) => value,
+            _ => return Err(()),
+        };
+
+        let (old, new) = {
+            let nutrition = self.ecs_mut().nutrition.get_mut(e).ok_or(())?;
+            let old = nutrition.hunger();
+            nutrition.satiation = (nutrition.satiation + value).min(Nutrition::MAX);
+            (old, nutrition.hunger())
+        };
+
+        msg!(self, "[One] eat[s] [another].")
+            .subject(e)
+            .object(item)
+            .send();
+        self.use_up_one(item);
+        if old != new {
+            self.rebuild_stats(e);
+        }
+        self.end_turn(e);
+        Ok(())
+    }
+
     fn entity_take(&mut self, e: Entity, item: Entity) -> Result<(), ()> {
-        // Only mobs can take items.
-        if !self.is_mob(e) {
+        // Only mobs with hands can take items.
+        if !self.is_mob(e) || !self.can_manipulate(e) {
             return Err(());
         }
 
@@ -230,16 +501,23 @@ pub trait Mutate: Query + Terraform + Sized {
             panic!("Trying to pick up an entity you are inside of. This shouldn't happen");
         }
 
-        if let Some(slot) = self.free_bag_slot(e) {
-            self.equip_item(item, e, slot);
-            if self.is_player(e) {
-                msg!(self, "[One] pick[s] up [another].")
-                    .subject(e)
-                    .object(item)
-                    .send();
-            }
+        self.add_to_inventory(e, item)?;
+        self.emit(Event::PickUp { entity: e, item });
+        self.end_turn(e);
+        Ok(())
+    }
 
-            self.end_turn(e);
+    /// Put an item in a free bag slot, or merge it into a matching stack.
+    fn add_to_inventory(&mut self, e: Entity, item: Entity) -> Result<(), ()> {
+        if let Some(stack) = self.matching_stack(e, item) {
+            let count = self.item_count(item);
+            if let Some(i) = self.ecs_mut().item.get_mut(stack) {
+                i.count += count;
+            }
+            self.kill_entity(item);
+            Ok(())
+        } else if let Some(slot) = self.free_bag_slot(e) {
+            self.equip_item(item, e, slot);
             Ok(())
         } else {
             // No more inventory space
@@ -247,6 +525,136 @@ pub trait Mutate: Query + Terraform + Sized {
         }
     }
 
+    /// Throw a carried item in a direction.
+    ///
+    /// Thrown weapons hurt the mob they hit and thrown potions shatter and splash their magic on
+    /// everything near where they land.
+    fn entity_throw(&mut self, e: Entity, item: Entity, dir: Dir6) -> Result<(), ()> {
+        const THROW_RANGE: u32 = 6;
+        const SPLASH_RADIUS: u32 = 1;
+
+        if !self.is_mob(e) || !self.can_manipulate(e) || !self.entity_contains(e, item) {
+            return Err(());
+        }
+        let origin = self.location(e).ok_or(())?;
+        let to = self.projected_explosion_center(origin, dir, THROW_RANGE);
+
+        // Only one item of a stack gets thrown.
+        let item = if self.item_count(item) > 1 {
+            self.split_stack(item, 1, origin).ok_or(())?
+        } else {
+            item
+        };
+        // Land like a dropped item, spreading out from any items already at the target.
+        self.place_entity(item, to);
+        // The item might have been equipped.
+        self.rebuild_stats(e);
+        self.emit(Event::Throw {
+            thrower: e,
+            item,
+            from: origin,
+            to,
+        });
+
+        let is_weapon = match self.equip_type(item) {
+            Some(EquipType::Melee) | Some(EquipType::Ranged) => true,
+            _ => false,
+        };
+        match (self.mob_at(to), self.item_type(item)) {
+            (Some(target), _) if is_weapon => {
+                // Same formula as melee, with the weapon's power doing the damage.
+                let advantage = self.stats(e).attack - self.stats(target).defense
+                    + 2 * self.stats(target).armor;
+                let damage = attack_damage(roll(self.rng()), advantage, 5 + self.stats(item).power);
+
+                if damage == 0 {
+                    self.emit(Event::Miss {
+                        attacker: e,
+                        target,
+                    });
+                } else {
+                    self.emit(Event::Hit {
+                        attacker: e,
+                        target,
+                        damage,
+                    });
+                }
+                self.damage(target, damage, Damage::Physical, Some(e));
+            }
+            (_, Some(ItemType::UntargetedUsable(effect))) if self.is_potion(item) => {
+                msg!(self, "[One] shatter[s]!").subject(item).send();
+                if let Some(splash) = effect.self_effect() {
+                    let volume = self.sphere_volume(to, SPLASH_RADIUS);
+                    self.emit(Event::Spell {
+                        caster: Some(e),
+                        effect,
+                        volume: volume.clone(),
+                    });
+                    self.apply_effect(&splash, &volume, Some(e));
+                }
+                if self.is_player(e) {
+                    self.identify(item);
+                }
+                self.kill_entity(item);
+            }
+            _ => {}
+        }
+
+        self.end_turn(e);
+        Ok(())
+    }
+
+    /// Put an item the entity is carrying into a container.
+    fn entity_put_in(&mut self, e: Entity, item: Entity, container: Entity) -> Result<(), ()> {
+        if !self.is_mob(e) || !self.can_manipulate(e) {
+            return Err(());
+        }
+        if !self.reachable_containers(e).contains(&container) || !self.entity_contains(e, item) {
+            return Err(());
+        }
+        // Can't put a container inside itself.
+        if item == container || self.entity_contains(item, container) {
+            return Err(());
+        }
+
+        let capacity = self.capacity(container).ok_or(())?;
+        if self.contents(container).len() as u32 >= capacity {
+            msg!(self, "[One] [is] full.").subject(container).send();
+            return Err(());
+        }
+
+        self.stow_item(item, container);
+        // The item might have been equipped.
+        self.rebuild_stats(e);
+        self.emit(Event::PutIn {
+            entity: e,
+            item,
+            container,
+        });
+        self.end_turn(e);
+        Ok(())
+    }
+
+    /// Take an item out of a container and put it in the entity's inventory.
+    fn entity_take_out(&mut self, e: Entity, item: Entity) -> Result<(), ()> {
+        if !self.is_mob(e) || !self.can_manipulate(e) {
+            return Err(());
+        }
+        let container = self.entity_container(item).ok_or(())?;
+        if !self.reachable_containers(e).contains(&container) {
+            return Err(());
+        }
+
+        self.add_to_inventory(e, item)?;
+        self.emit(Event::TakeOut {
+            entity: e,
+            item,
+            container,
+        });
+        self.end_turn(e);
+        Ok(())
+    }
+
     /// Cast an undirected spell
     fn cast_spell(
         &mut self,
@@ -254,8 +662,12 @@ pub trait Mutate: Query + Terraform + Sized {
         effect: Entity,
         caster: Option<Entity>,
     ) -> Result<(), ()> {
-        if let ItemType::UntargetedUsable(effect) = self.ecs().item.get(effect).ok_or(())?.item_type
+        if let ItemType::UntargetedUsable(effect) | ItemType::Spell(effect) =
+            self.ecs().item.get(effect).ok_or(())?.item_type
         {
+            // Effects that apply to the caster.
+            let self_effect = effect.self_effect();
+
             match effect {
                 MagicEffect::Lightning => {
                     const LIGHTNING_RANGE: u32 = 4;
@@ -279,13 +691,29 @@ pub trait Mutate: Query + Terraform + Sized {
                     if let Some(target) = target.pop() {
                         msg!(self, "There is a peal of thunder.").send();
                         let loc = self.location(*target).unwrap();
-                        self.apply_effect(&LIGHTNING_EFFECT, &Volume::point(loc), caster);
+                        let volume = Volume::point(loc);
+                        self.emit(Event::Spell {
+                            caster,
+                            effect,
+                            volume: volume.clone(),
+                        });
+                        self.apply_effect(&LIGHTNING_EFFECT, &volume, caster);
                     } else {
                         msg!(self, "The spell fizzles.").send();
                     }
                 }
                 _ => {
-                    msg!(self, "TODO cast untargeted spell {:?}", effect).send();
+                    if let Some(self_effect) = self_effect {
+                        let volume = Volume::point(origin);
+                        self.emit(Event::Spell {
+                            caster,
+                            effect,
+                            volume: volume.clone(),
+                        });
+                        self.apply_effect(&self_effect, &volume, caster);
+                    } else {
+                        msg!(self, "The spell fizzles.").send();
+                    }
                 }
             }
             caster.map(|e| self.end_turn(e));
@@ -303,7 +731,18 @@ pub trait Mutate: Query + Terraform + Sized {
         effect: Entity,
         caster: Option<Entity>,
     ) -> Result<(), ()> {
-        if let ItemType::TargetedUsable(effect) = self.ecs().item.get(effect).ok_or(())?.item_type {
+        // Wands need to be pointed at things, spells are cast without hands.
+        let is_spell = match self.item_type(effect) {
+            Some(ItemType::Spell(_)) => true,
+            _ => false,
+        };
+        if !is_spell && caster.map_or(false, |e| !self.can_manipulate(e)) {
+            return Err(());
+        }
+
+        if let ItemType::TargetedUsable(effect) | ItemType::Spell(effect) =
+            self.ecs().item.get(effect).ok_or(())?.item_type
+        {
             match effect {
                 MagicEffect::Fireball => {
                     const FIREBALL_RANGE: u32 = 9;
@@ -314,13 +753,29 @@ pub trait Mutate: Query + Terraform + Sized {
                     };
                     let center = self.projected_explosion_center(origin, dir, FIREBALL_RANGE);
                     let volume = self.sphere_volume(center, FIREBALL_RADIUS);
+                    self.emit(Event::Spell {
+                        caster,
+                        effect,
+                        volume: volume.clone(),
+                    });
                     self.apply_effect(&FIREBALL_EFFECT, &volume, caster);
                 }
-                MagicEffect::Confuse => {
-                    const CONFUSION_RANGE: u32 = 9;
+                MagicEffect::Confuse | MagicEffect::Sleep | MagicEffect::Fear => {
+                    const BOLT_RANGE: u32 = 9;
 
-                    let center = self.projected_explosion_center(origin, dir, CONFUSION_RANGE);
-                    self.apply_effect(&Effect::Confuse, &Volume::point(center), caster);
+                    let bolt_effect = match effect {
+                        MagicEffect::Sleep => Effect::Status(Status::Asleep, 120),
+                        MagicEffect::Fear => Effect::Status(Status::Afraid, 120),
+                        _ => Effect::Confuse,
+                    };
+                    let center = self.projected_explosion_center(origin, dir, BOLT_RANGE);
+                    let volume = Volume::point(center);
+                    self.emit(Event::Spell {
+                        caster,
+                        effect,
+                        volume: volume.clone(),
+                    });
+                    self.apply_effect(&bolt_effect, &volume, caster);
                 }
                 _ => {
                     msg!(self, "TODO cast directed spell {:?}", effect).send();
@@ -333,15 +788,95 @@ pub trait Mutate: Query + Terraform + Sized {
         }
     }
 
+    /// Cast a spell the entity knows, paying for it with mana.
+    ///
+    /// Directed spells need a direction and undirected ones must not have one.
+    fn entity_cast(&mut self, e: Entity, spell: Entity, dir: Option<Dir6>) -> Result<(), ()> {
+        let effect = match self.item_type(spell) {
+            Some(ItemType::Spell(effect)) => effect,
+            _ => return Err(()),
+        };
+        let origin = self.location(e).ok_or(())?;
+        if !self.ecs().mana.contains(e) {
+            return Err(());
+        }
+
+        let cost = effect.mana_cost();
+        if self.mana(e) < cost {
+            msg!(self, "[One] lack[s] the mana to cast {}.", effect.spell_name())
+                .subject(e)
+                .send();
+            return Err(());
+        }
+
+        match dir {
+            Some(dir) if effect.is_directed() => {
+                self.cast_directed_spell(origin, dir, spell, Some(e))?
+            }
+            None if !effect.is_directed() => self.cast_spell(origin, spell, Some(e))?,
+            _ => return Err(()),
+        }
+
+        self.ecs_mut().mana[e].spent += cost;
+        Ok(())
+    }
+
+    /// Read a spellbook and put the spell it teaches in a free spell slot.
+    fn entity_learn_spell(&mut self, e: Entity, book: Entity) -> Result<(), ()> {
+        let effect = match self.item_type(book) {
+            Some(ItemType::Spellbook(effect)) => effect,
+            _ => return Err(()),
+        };
+        let loc = self.location(e).ok_or(())?;
+
+        if self.knows_spell(e, effect) {
+            msg!(self, "[One] already know[s] {}.", effect.spell_name())
+                .subject(e)
+                .send();
+            return Err(());
+        }
+
+        let slot = Slot::equipped_iter()
+            .find(|&&x| x.accepts(EquipType::Spell) && self.entity_equipped(e, x).is_none())
+            .cloned();
+        let slot = match slot {
+            Some(slot) => slot,
+            None => {
+                msg!(self, "[One] can't learn any more spells.").subject(e).send();
+                return Err(());
+            }
+        };
+
+        let loadout = Loadout::new()
+            .c(Desc::new(effect.spell_name(), Icon::Book))
+            .c(Item {
+                item_type: ItemType::Spell(effect),
+                charges: 1,
+                cursed: false,
+                curse_known: false,
+                count: 1,
+            });
+        let spell = self.spawn(&loadout, loc);
+        self.equip_item(spell, e, slot);
+
+        msg!(self, "[One] learn[s] the spell of {}.", effect.spell_name())
+            .subject(e)
+            .send();
+        self.use_up_one(book);
+        self.end_turn(e);
+        Ok(())
+    }
+
     /// The entity spends its action waiting.
     fn idle(&mut self, e: Entity) {
-        if self.consume_nutrition(e) {
+        if self.hunger(e) != Some(Hunger::Starving) {
             if let Some(regen) = self.tick_regeneration(e) {
                 self.push_event(Event::Damage {
                     entity: e,
                     amount: -regen,
                 });
             }
+            self.tick_mana(e);
         }
         self.end_turn(e);
     }
@@ -352,7 +887,24 @@ pub trait Mutate: Query + Terraform + Sized {
         let origin = self.location(e).ok_or(())?;
         let loc = origin.jump(self, dir);
         if self.can_enter(e, loc) {
+            if self.terrain(loc).is_door() {
+                // Bumping into a closed door opens it.
+                return self.open_door(e, loc);
+            }
+
             self.place_entity(e, loc);
+            self.emit(Event::Move {
+                entity: e,
+                from: origin,
+                to: loc,
+            });
+            if loc.z != origin.z {
+                self.emit(Event::Stairs {
+                    entity: e,
+                    from: origin,
+                    to: loc,
+                });
+            }
 
             let delay = self.action_delay(e);
             debug_assert!(delay > 0);
@@ -361,6 +913,7 @@ pub trait Mutate: Query + Terraform + Sized {
                 anim.tween_current = delay - 1;
                 anim.tween_max = delay;
             }
+            self.trigger_instants(e, loc);
             self.end_turn(e);
             return Ok(());
         }
@@ -368,6 +921,143 @@ pub trait Mutate: Query + Terraform + Sized {
         Err(())
     }
 
+    /// Set off instant items and traps at a location the entity stepped on.
+    fn trigger_instants(&mut self, e: Entity, loc: Location) {
+        const SPLASH_RADIUS: u32 = 1;
+
+        for i in self.entities_at(loc) {
+            let magic = match self.instant_effect(i) {
+                Some(magic) => magic,
+                None => continue,
+            };
+
+            if self.is_player(e) || self.player_sees(loc) {
+                if self.is_trap(i) {
+                    msg!(self, "[One] trigger[s] [another]!")
+                        .subject(e)
+                        .object(i)
+                        .send();
+                } else {
+                    msg!(self, "[One] step[s] on [another].")
+                        .subject(e)
+                        .object(i)
+                        .send();
+                }
+            }
+
+            // Instants are consumed whether or not the magic does anything.
+            self.use_up_one(i);
+
+            if let Some(effect) = magic.step_effect() {
+                let volume = if magic == MagicEffect::Fireball {
+                    self.sphere_volume(loc, SPLASH_RADIUS)
+                } else {
+                    Volume::point(loc)
+                };
+                self.emit(Event::Spell {
+                    caster: None,
+                    effect: magic,
+                    volume: volume.clone(),
+                });
+                self.apply_effect(&effect, &volume, None);
+            }
+        }
+    }
+
+    /// Look for hidden traps around the entity.
+    fn entity_search(&mut self, e: Entity) -> Result<(), ()> {
+        const SEARCH_RADIUS: u32 = 2;
+        const FIND_CHANCE_ONE_IN: u32 = 2;
+
+        let origin = self.location(e).ok_or(())?;
+        let traps: Vec<Entity> = self
+            .sphere_volume(origin, SEARCH_RADIUS)
+            .0
+            .into_iter()
+            .flat_map(|loc| self.entities_at(loc))
+            .filter(|&t| self.is_trap(t) && self.is_hidden(t))
+            .collect();
+
+        for trap in traps {
+            if self.rng().one_chance_in(FIND_CHANCE_ONE_IN) {
+                self.reveal_trap(trap);
+                if self.is_player(e) {
+                    msg!(self, "[One] find[s] [another].")
+                        .subject(e)
+                        .object(trap)
+                        .send();
+                }
+            }
+        }
+        self.end_turn(e);
+        Ok(())
+    }
+
+    /// Make a hidden trap visible.
+    fn reveal_trap(&mut self, trap: Entity) {
+        if let Some(t) = self.ecs_mut().trap.get_mut(trap) {
+            t.hidden = false;
+        }
+    }
+
+    /// Open a closed door, using up a key if the door is locked.
+    fn open_door(&mut self, e: Entity, loc: Location) -> Result<(), ()> {
+        let t = self.terrain(loc);
+        if !t.is_door() || !self.can_manipulate(e) {
+            return Err(());
+        }
+
+        if t.is_locked_door() {
+            if let Some(key) = self.find_key(e) {
+                msg!(self, "[One] unlock[s] the door.").subject(e).send();
+                self.kill_entity(key);
+            } else {
+                if self.is_player(e) {
+                    msg!(self, "The door is locked.").send();
+                }
+                return Err(());
+            }
+        }
+
+        self.set_terrain(loc, Terrain::OpenDoor);
+        self.emit(Event::DoorOpened { entity: e, loc });
+        self.make_noise(loc, DOOR_LOUDNESS, e);
+        self.refresh_fov();
+        self.end_turn(e);
+        Ok(())
+    }
+
+    /// Close an open door.
+    ///
+    /// Fails if there's anything in the doorway.
+    fn close_door(&mut self, e: Entity, loc: Location) -> Result<(), ()> {
+        if self.terrain(loc) != Terrain::OpenDoor || !self.can_manipulate(e) {
+            return Err(());
+        }
+        if !self.entities_at(loc).is_empty() {
+            return Err(());
+        }
+
+        self.set_terrain(loc, Terrain::Door);
+        self.emit(Event::DoorClosed { entity: e, loc });
+        self.make_noise(loc, DOOR_LOUDNESS, e);
+        self.refresh_fov();
+        self.end_turn(e);
+        Ok(())
+    }
+
+    /// Recompute everyone's field of view after terrain that blocks sight has changed.
+    fn refresh_fov(&mut self) {
+        let viewers: Vec<Entity> = self
+            .entities()
+            .filter(|&&e| self.ecs().map_memory.contains(e))
+            .cloned()
+            .collect();
+        for e in viewers {
+            self.do_fov(e);
+        }
+    }
+
     fn really_melee(&mut self, e: Entity, dir: Dir6) -> Result<(), ()> {
         if let Some(loc) = self.location(e) {
             if let Some(target) = self.mob_at(loc.jump(self, dir)) {
@@ -378,17 +1068,32 @@ pub trait Mutate: Query + Terraform + Sized {
                 let damage = attack_damage(roll(self.rng()), advantage, 5 + self.stats(e).power);
 
                 if damage == 0 {
-                    msg!(self, "[One] miss[es] [another].")
-                        .subject(e)
-                        .object(target)
-                        .send();
+                    self.emit(Event::Miss {
+                        attacker: e,
+                        target,
+                    });
                 } else {
-                    msg!(self, "[One] hit[s] [another] for {}.", damage)
-                        .subject(e)
-                        .object(target)
-                        .send();
+                    self.emit(Event::Hit {
+                        attacker: e,
+                        target,
+                        damage,
+                    });
                 }
                 self.damage(target, damage, Damage::Physical, Some(e));
+
+                if damage > 0
+                    && self.is_alive(target)
+                    && self.has_intrinsic(e, Intrinsic::Poisonous)
+                {
+                    self.gain_status(target, Status::Poisoned, 36);
+                }
+
+                // Mobs hearing the fight will come after whoever they have a grudge against.
+                self.make_noise(loc, COMBAT_LOUDNESS, e);
+                if self.is_alive(target) {
+                    self.make_noise(loc, COMBAT_LOUDNESS, target);
+                }
+
                 self.end_turn(e);
                 return Ok(());
             }
@@ -396,6 +1101,82 @@ pub trait Mutate: Query + Terraform + Sized {
         Err(())
     }
 
+    /// Fire a ranged attack in a direction.
+    ///
+    /// The shot hits the first mob in its path, and is stopped by terrain that blocks shots.
+    fn really_fire(&mut self, e: Entity, dir: Dir6) -> Result<(), ()> {
+        let stats = self.stats(e);
+        if stats.ranged_range == 0 {
+            return Err(());
+        }
+        let origin = self.location(e).ok_or(())?;
+
+        // Ranged weapons need ammo, innate ranged attacks don't.
+        let ammo = if self.entity_equipped(e, Slot::Ranged).is_some() {
+            match self.find_ammo(e) {
+                Some(ammo) => Some(ammo),
+                None => {
+                    if self.is_player(e) {
+                        msg!(self, "[One] [is] out of ammo.").subject(e).send();
+                    }
+                    return Err(());
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut loc = origin;
+        let mut target = None;
+        for _ in 0..stats.ranged_range {
+            let next = loc.jump(self, dir);
+            if self.terrain(next).blocks_shot() {
+                break;
+            }
+            loc = next;
+            if let Some(mob) = self.mob_at(loc) {
+                target = Some(mob);
+                break;
+            }
+        }
+
+        self.emit(Event::Shot {
+            shooter: e,
+            from: origin,
+            to: loc,
+        });
+        if let Some(ammo) = ammo {
+            self.use_up_one(ammo);
+        }
+
+        if let Some(target) = target {
+            let advantage = stats.attack - self.stats(target).defense;
+            let damage = attack_damage(roll(self.rng()), advantage, stats.ranged_power);
+
+            if damage == 0 {
+                self.emit(Event::Miss {
+                    attacker: e,
+                    target,
+                });
+            } else {
+                self.emit(Event::Hit {
+                    attacker: e,
+                    target,
+                    damage,
+                });
+            }
+            self.damage(target, damage, Damage::Physical, Some(e));
+
+            self.make_noise(loc, COMBAT_LOUDNESS, e);
+            if self.is_alive(target) {
+                self.make_noise(loc, COMBAT_LOUDNESS, target);
+            }
+        }
+
+        self.end_turn(e);
+        Ok(())
+    }
+
     /// Randomly make a confused mob move erratically.
     ///
     /// Return true if confusion kicked in.
@@ -431,12 +1212,20 @@ pub trait Mutate: Query + Terraform + Sized {
             self.notify_attacked_by(e, attacker);
         }
 
+        if amount > 0 {
+            // Getting hurt is a rude awakening.
+            self.remove_status(e, Status::Asleep);
+        }
+
         let max_hp = self.max_hp(e);
+        let amount = self.resisted_damage(e, amount, damage_type);
 
         let mut kill = false;
         if let Some(health) = self.ecs_mut().health.get_mut(e) {
             if amount > 0 {
-                health.wounds += amount;
+                let (armor_lost, wounds) = armor_absorb(amount, health.armor, damage_type);
+                health.armor -= armor_lost;
+                health.wounds += wounds;
 
                 if health.wounds > max_hp {
                     kill = true;
@@ -445,23 +1234,76 @@ pub trait Mutate: Query + Terraform + Sized {
         }
 
         if kill {
-            if let Some(loc) = self.location(e) {
-                if self.player_sees(loc) {
-                    // TODO: message templating
-                    msg!(
-                        self,
-                        "[One] {}.",
-                        match damage_type {
-                            Damage::Physical => "die[s]",
-                            Damage::Fire => "burn[s] to ash",
-                            Damage::Electricity => "[is] electrocuted",
-                            Damage::Cold => "shatter[s] to frozen pieces",
-                        }
-                    ).subject(e)
-                        .send();
-                }
+            self.emit(Event::Death {
+                entity: e,
+                damage: damage_type,
+            });
+            if let Some(killer) = source.filter(|&k| k != e) {
+                let xp = self.kill_xp(e);
+                self.gain_xp(killer, xp);
             }
+            self.leave_corpse(e, damage_type);
+            let loc = self.location(e);
             self.kill_entity(e);
+
+            if let Some(loc) = loc {
+                if self.has_intrinsic(e, Intrinsic::Deathsplosion) {
+                    self.deathsplosion(e, loc);
+                }
+            }
+        }
+    }
+
+    /// Blow up a dead entity.
+    ///
+    /// Explosions that set off other explosions don't recurse. They go to a queue and are
+    /// processed in the order the entities died, after the current explosion has hit everything
+    /// in its area.
+    fn deathsplosion(&mut self, e: Entity, loc: Location) {
+        const EXPLOSION_RADIUS: u32 = 2;
+
+        if !self.queue_explosion(e, loc) {
+            // Someone further up the stack is already processing the queue.
+            return;
+        }
+
+        while let Some((e, loc)) = self.next_explosion() {
+            let effect = Effect::Hit {
+                amount: self.stats(e).power.max(1) as u32,
+                damage: Damage::Fire,
+            };
+            let volume = self.sphere_volume(loc, EXPLOSION_RADIUS);
+            self.emit(Event::Explosion {
+                entity: e,
+                volume: volume.clone(),
+            });
+            self.apply_effect(&effect, &volume, None);
+        }
+    }
+
+    /// Drop an edible corpse where a mob died.
+    fn leave_corpse(&mut self, e: Entity, damage_type: Damage) {
+        // Nothing edible is left after burning or electrocution.
+        if damage_type == Damage::Fire || damage_type == Damage::Electricity {
+            return;
+        }
+        if !self.is_npc(e) {
+            return;
+        }
+
+        if let Some(loc) = self.location(e) {
+            let name = format!("{} corpse", self.entity_name(e));
+            let value = 100 * self.stats(e).power.max(1) as u32;
+            let loadout = Loadout::new()
+                .c(Desc::new(&name, Icon::Corpse))
+                .c(Item {
+                    item_type: ItemType::Food(value),
+                    charges: 1,
+                    cursed: false,
+                    curse_known: false,
+                    count: 1,
+                });
+            self.spawn(&loadout, loc);
         }
     }
 
@@ -470,9 +1312,15 @@ pub trait Mutate: Query + Terraform + Sized {
     /// Return amount of health gained, or None if at full health.
     fn tick_regeneration(&mut self, e: Entity) -> Option<i32> {
         let max_hp = self.max_hp(e);
+        let max_armor = self.stats(e).armor;
         let increase = (max_hp / 30).max(1);
 
         if let Some(health) = self.ecs_mut().health.get_mut(e) {
+            // Armor gets patched up at the same time as wounds heal.
+            if health.armor < max_armor {
+                health.armor += 1;
+            }
+
             if health.wounds > 0 {
                 let increase = increase.min(health.wounds);
                 health.wounds -= increase;
@@ -485,8 +1333,35 @@ pub trait Mutate: Query + Terraform + Sized {
         }
     }
 
+    /// Recover a point of spent mana.
+    fn tick_mana(&mut self, e: Entity) {
+        if let Some(mana) = self.ecs_mut().mana.get_mut(e) {
+            if mana.spent > 0 {
+                mana.spent -= 1;
+            }
+        }
+    }
+
     fn spawn(&mut self, loadout: &Loadout, loc: Location) -> Entity;
 
+    /// Spawn an entity from map generation.
+    ///
+    /// Items generated in the same cell as a container are put inside the container.
+    fn spawn_generated(&mut self, loadout: &Loadout, loc: Location) -> Entity {
+        let e = self.spawn(loadout, loc);
+        if self.is_item(e) {
+            let container = self.entities_at(loc).into_iter().find(|&c| {
+                c != e && self
+                    .capacity(c)
+                    .map_or(false, |n| (self.contents(c).len() as u32) < n)
+            });
+            if let Some(container) = container {
+                self.stow_item(e, container);
+            }
+        }
+        e
+    }
+
     fn deploy_prefab(&mut self, origin: Location, prefab: &mapsave::Prefab) {
         for (&p, &(ref terrain, _)) in prefab.iter() {
             let loc = origin + p;
@@ -509,7 +1384,7 @@ pub trait Mutate: Query + Terraform + Sized {
                     self.spawn_player(loc);
                 } else {
                     let loadout = spawn.sample(self.rng());
-                    self.spawn(&loadout, loc);
+                    self.spawn_generated(&loadout, loc);
                 }
             }
         }
@@ -534,29 +1409,139 @@ pub trait Mutate: Query + Terraform + Sized {
             let loadout = spec::PLAYER_SPAWN
                 .sample(self.rng())
                 .c(Brain::player())
-                .c(MapMemory::default());
+                .c(MapMemory::default())
+                .c(Nutrition::default())
+                .c(Experience::default())
+                .c(Mana::default());
             let player = self.spawn(&loadout, loc);
             self.set_player(Some(player));
+
+            // Start out with a torch to light the way underground.
+            let loadout = spec::TORCH_SPAWN.sample(self.rng());
+            let torch = self.spawn(&loadout, loc);
+            if let Some(slot) = self.free_equip_slot(player, torch) {
+                self.equip_item(torch, player, slot);
+            }
         }
     }
 
     fn apply_effect_to_entity(&mut self, effect: &Effect, target: Entity, source: Option<Entity>) {
         use effect::Effect::*;
         match *effect {
-            Heal(_amount) => {
-                unimplemented!();
+            Heal(amount) => {
+                let mut healed = 0;
+                if let Some(health) = self.ecs_mut().health.get_mut(target) {
+                    healed = (amount as i32).min(health.wounds);
+                    health.wounds -= healed;
+                }
+
+                if healed > 0 {
+                    if self.location(target).map_or(false, |loc| self.player_sees(loc)) {
+                        msg!(self, "[One] look[s] healthier.").subject(target).send();
+                    }
+                    self.push_event(Event::Damage {
+                        entity: target,
+                        amount: -healed,
+                    });
+                }
             }
             Hit { amount, damage } => {
                 self.damage(target, amount as i32, damage, source);
             }
             Confuse => {
                 self.gain_status(target, Status::Confused, 40);
-                msg!(self, "[One] [is] confused.").subject(target).send();
+            }
+            Status(status, duration) => {
+                self.gain_status(target, status, duration);
             }
             MagicMap => {
-                unimplemented!();
+                if let Some(sector) = self.location(target).map(|loc| loc.sector()) {
+                    if let Some(memory) = self.ecs_mut().map_memory.get_mut(target) {
+                        memory.remembered.extend(sector.iter());
+                    }
+                    if self.is_player(target) {
+                        // Detect the traps in the mapped area.
+                        let traps: Vec<Entity> = sector
+                            .iter()
+                            .flat_map(|loc| self.entities_at(loc))
+                            .filter(|&e| self.is_trap(e))
+                            .collect();
+                        for trap in traps {
+                            self.reveal_trap(trap);
+                        }
+
+                        msg!(self, "[One] sense[s] the surroundings.")
+                            .subject(target)
+                            .send();
+                    }
+                }
+            }
+            RemoveCurse => {
+                let mut uncursed = false;
+                for item in self.entities_in(target) {
+                    if let Some(i) = self.ecs_mut().item.get_mut(item) {
+                        if i.cursed {
+                            i.cursed = false;
+                            i.curse_known = false;
+                            uncursed = true;
+                        }
+                    }
+                }
+
+                if uncursed && self.is_player(target) {
+                    msg!(self, "[One] feel[s] a malevolent aura fade.")
+                        .subject(target)
+                        .send();
+                }
+            }
+        }
+    }
+
+    /// Learn the kind of an item by using it.
+    fn identify(&mut self, item: Entity) {
+        if self.unidentified_name(item).is_none() {
+            return;
+        }
+
+        let name = self.spawn_name(item).unwrap().to_string();
+        self.mark_identified(&name);
+        if let Some(player) = self.player() {
+            let a_name = self.noun(item).a_name();
+            msg!(self, "[One] recognize[s] {}.", a_name)
+                .subject(player)
+                .send();
+        }
+    }
+
+    /// Check whether a curse keeps an equipped item from being taken off.
+    ///
+    /// Tells the wearer about it if so.
+    fn is_stuck_with(&mut self, e: Entity, item: Entity) -> bool {
+        if !self.ecs().item.get(item).map_or(false, |i| i.cursed) {
+            return false;
+        }
+
+        self.reveal_curse(item);
+        msg!(self, "[One] can't remove [another].")
+            .subject(e)
+            .object(item)
+            .send();
+        true
+    }
+
+    /// Let the wielder find out whether the item is cursed.
+    fn reveal_curse(&mut self, item: Entity) {
+        let mut revealed = false;
+        if let Some(i) = self.ecs_mut().item.get_mut(item) {
+            if i.cursed && !i.curse_known {
+                i.curse_known = true;
+                revealed = true;
             }
         }
+
+        if revealed {
+            msg!(self, "[One] [is] cursed!").subject(item).send();
+        }
     }
 
     fn apply_effect_to(&mut self, effect: &Effect, loc: Location, source: Option<Entity>) {
@@ -585,22 +1570,94 @@ pub trait Mutate: Query + Terraform + Sized {
 
         if emptied {
             if self.destroy_after_use(item) {
-                self.kill_entity(item);
+                self.use_up_one(item);
+            }
+        }
+    }
+
+    /// Destroy a used up item, or just take one off the top if it's a stack.
+    fn use_up_one(&mut self, item: Entity) {
+        if let Some(i) = self.ecs_mut().item.get_mut(item) {
+            if i.count > 1 {
+                i.count -= 1;
+                // The next one in the stack is fresh.
+                i.charges = 1;
+                return;
             }
         }
+        self.kill_entity(item);
+    }
+
+    /// Split some items off a stack into a new entity placed at location.
+    ///
+    /// Returns the new entity, or `None` if the stack doesn't have more than `count` items.
+    fn split_stack(&mut self, item: Entity, count: u32, loc: Location) -> Option<Entity> {
+        let total = self.item_count(item);
+        if count == 0 || count >= total {
+            return None;
+        }
+
+        let loadout = {
+            let ecs = self.ecs();
+            let mut split = ecs.item.get(item)?.clone();
+            split.count = count;
+            let mut loadout = Loadout::new().c(split);
+            if let Some(desc) = ecs.desc.get(item) {
+                loadout = loadout.c(desc.clone());
+            }
+            if let Some(&stats) = ecs.stats.get(item) {
+                loadout = loadout.c(stats);
+            }
+            loadout
+        };
+
+        self.ecs_mut().item.get_mut(item)?.count = total - count;
+        Some(self.spawn(&loadout, loc))
     }
 
     /// Run autonomous updates on entity that happen each turn
     ///
     /// This runs regardless of the action speed or awakeness status of the entity. The exact same
     /// is run for player and AI entities.
-    fn heartbeat(&mut self, e: Entity) { self.tick_statuses(e); }
+    fn heartbeat(&mut self, e: Entity) {
+        const MANA_REGEN_INTERVAL: u64 = 60;
+
+        self.tick_statuses(e);
+        if self.get_tick() % MANA_REGEN_INTERVAL == 0 {
+            self.tick_mana(e);
+        }
+    }
+
+    /// Give experience to an entity that can gain levels, level it up if it's earned it.
+    fn gain_xp(&mut self, e: Entity, amount: u32) {
+        let (old, new) = match self.ecs_mut().experience.get_mut(e) {
+            Some(exp) => {
+                let old = exp.level;
+                exp.xp += amount;
+                exp.level = progression::level_for(exp.xp).max(old);
+                (old, exp.level)
+            }
+            None => return,
+        };
+
+        for level in (old + 1)..(new + 1) {
+            let gain = progression::level_gain(level);
+            if let Some(stats) = self.ecs_mut().stats.get_mut(e) {
+                stats.base = stats.base + gain;
+            }
+            self.emit(Event::LevelUp { entity: e, level });
+        }
+        if new > old {
+            self.rebuild_stats(e);
+        }
+    }
 
     fn gain_status(&mut self, e: Entity, status: Status, duration: u32) {
         if duration == 0 {
             return;
         }
 
+        let mut is_new = false;
         if let Some(statuses) = self.ecs_mut().status.get_mut(e) {
             if let Some(current_duration) = statuses.get(&status).cloned() {
                 if duration > current_duration {
@@ -608,27 +1665,92 @@ pub trait Mutate: Query + Terraform + Sized {
                     statuses.insert(status, duration);
                 }
             } else {
-                // TODO: Special stuff when status first goes into effect goes here
                 statuses.insert(status, duration);
+                is_new = true;
             }
         }
+
+        if is_new {
+            self.status_onset(e, status);
+        }
+    }
+
+    /// End a status before its duration runs out.
+    fn remove_status(&mut self, e: Entity, status: Status) {
+        let removed = self
+            .ecs_mut()
+            .status
+            .get_mut(e)
+            .map_or(false, |statuses| statuses.remove(&status).is_some());
+
+        if removed {
+            self.status_expire(e, status);
+        }
     }
 
     fn tick_statuses(&mut self, e: Entity) {
+        let mut active = Vec::new();
+        let mut remove = Vec::new();
         if let Some(statuses) = self.ecs_mut().status.get_mut(e) {
-            let mut remove = Vec::new();
-
             for (k, d) in statuses.iter_mut() {
                 *d -= 1;
                 if *d == 0 {
                     remove.push(*k);
+                } else {
+                    active.push((*k, *d));
                 }
             }
 
-            // TODO: Special stuff when status goes out of effect for dropped statuses.
-            for k in remove.into_iter() {
-                statuses.remove(&k);
+            for k in &remove {
+                statuses.remove(k);
+            }
+        }
+
+        for (status, duration) in active {
+            self.status_tick(e, status, duration);
+        }
+
+        for status in remove {
+            self.status_expire(e, status);
+        }
+    }
+
+    /// Hook for when a status first goes into effect.
+    fn status_onset(&mut self, e: Entity, status: Status) {
+        // Delayed is an internal bookkeeping status that changes every turn.
+        if status != Status::Delayed {
+            self.emit(Event::StatusGained { entity: e, status });
+        }
+    }
+
+    /// Hook for every tick a status is in effect.
+    fn status_tick(&mut self, e: Entity, status: Status, duration: u32) {
+        // Apply effects about once per normal speed turn.
+        const STATUS_EFFECT_INTERVAL: u32 = 12;
+        const POISON_DAMAGE: i32 = 1;
+
+        if duration % STATUS_EFFECT_INTERVAL != 0 {
+            return;
+        }
+
+        match status {
+            Status::Poisoned => self.damage(e, POISON_DAMAGE, Damage::Poison, None),
+            Status::Regenerating => {
+                if let Some(regen) = self.tick_regeneration(e) {
+                    self.push_event(Event::Damage {
+                        entity: e,
+                        amount: -regen,
+                    });
+                }
             }
+            _ => {}
+        }
+    }
+
+    /// Hook for when a status goes out of effect.
+    fn status_expire(&mut self, e: Entity, status: Status) {
+        if status != Status::Delayed {
+            self.emit(Event::StatusExpired { entity: e, status });
         }
     }
 
@@ -651,15 +1773,557 @@ pub trait Mutate: Query + Terraform + Sized {
             }
         }
 
+        // Hunger makes you worse at everything.
+        if let Some(hunger) = self.hunger(e) {
+            stats.attack -= hunger.penalty();
+            stats.defense -= hunger.penalty();
+        }
+
         // Set the derived stats.
         self.ecs_mut().stats[e].actual = stats;
+
+        // Lose armor points from removed armor.
+        if let Some(health) = self.ecs_mut().health.get_mut(e) {
+            health.armor = health.armor.min(stats.armor).max(0);
+        }
     }
 
     /// Consume one unit of nutrition
     ///
     /// Return false if the entity has an empty stomach.
-    fn consume_nutrition(&mut self, _: Entity) -> bool {
-        // TODO nutrition system
-        true
+    fn consume_nutrition(&mut self, e: Entity) -> bool {
+        let (old, new) = if let Some(nutrition) = self.ecs_mut().nutrition.get_mut(e) {
+            let old = nutrition.hunger();
+            nutrition.satiation = nutrition.satiation.saturating_sub(1);
+            (old, nutrition.hunger())
+        } else {
+            // Entities without nutrition never go hungry.
+            return true;
+        };
+
+        if old != new {
+            self.rebuild_stats(e);
+            if self.is_player(e) {
+                match new {
+                    Hunger::Satiated => {}
+                    Hunger::Hungry => msg!(self, "[One] [is] getting hungry.").subject(e).send(),
+                    Hunger::Weak => msg!(self, "[One] [is] weak with hunger.").subject(e).send(),
+                    Hunger::Fainting => msg!(self, "[One] [is] fainting from hunger.")
+                        .subject(e)
+                        .send(),
+                    Hunger::Starving => msg!(self, "[One] [is] starving!").subject(e).send(),
+                }
+            }
+        }
+
+        new != Hunger::Starving
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Mutate;
+    use calx::Dir6;
+    use command::Command;
+    use components::{
+        BrainState, Desc, Health, Hunger, Icon, Item, Nutrition, StatsComponent, Status,
+    };
+    use effect::{Damage, Effect};
+    use event::Event;
+    use item::{ItemType, MagicEffect};
+    use location::Location;
+    use query::Query;
+    use stats::{Intrinsic, Stats};
+    use terraform::{Terraform, TerrainQuery};
+    use terrain::Terrain;
+    use test_util::{clear_line, give, new_world, slot_of, spawn};
+    use world::Loadout;
+
+    #[test]
+    fn test_noise_wakes_mobs() {
+        let (mut world, player, loc) = new_world();
+        let door = loc.jump(&world, Dir6::North);
+        world.set_terrain(door, Terrain::Door);
+
+        let near_loc = loc.jump(&world, Dir6::South);
+        let far_loc = Location::new(loc.x, loc.y + 10, loc.z);
+        let mut mobs = Vec::new();
+        for &mob_loc in &[near_loc, far_loc] {
+            world.set_terrain(mob_loc, Terrain::Ground);
+            let mob = spawn(&mut world, "dreg", mob_loc);
+            assert_eq!(world.brain_state(mob), Some(BrainState::Asleep));
+            mobs.push(mob);
+        }
+
+        // Only the mob within earshot of the door hears it.
+        world.open_door(player, door).unwrap();
+        assert_eq!(world.brain_state(mobs[0]), Some(BrainState::Hunting(player)));
+        assert_eq!(world.brain_state(mobs[1]), Some(BrainState::Asleep));
+    }
+
+    #[test]
+    fn test_ranged_fire() {
+        let (mut world, player, loc) = new_world();
+        let bow = give(&mut world, "bow");
+        let slot = slot_of(&world, player, bow);
+        world.equip(slot).unwrap();
+        assert!(world.stats(player).ranged_range > 0);
+
+        let line = clear_line(&mut world, loc, Dir6::North, 4);
+        let mob = spawn(&mut world, "dreg", line[3]);
+        // Keep the mob from moving around between the shots.
+        world.gain_status(mob, Status::Asleep, 1000);
+
+        // The bow needs arrows.
+        assert!(world.fire(Dir6::North).is_err());
+        for _ in 0..2 {
+            give(&mut world, "arrow");
+        }
+
+        let shots_at = |events: &[Event]| -> Vec<Location> {
+            events
+                .iter()
+                .filter_map(|e| match *e {
+                    Event::Shot { to, .. } => Some(to),
+                    _ => None,
+                })
+                .collect()
+        };
+        let attacked = |events: &[Event]| {
+            events.iter().any(|e| match *e {
+                Event::Hit { target, .. } | Event::Miss { target, .. } => target == mob,
+                _ => false,
+            })
+        };
+
+        // A wall in between stops the shot.
+        world.set_terrain(line[2], Terrain::Wall);
+        let events = world.fire(Dir6::North).unwrap();
+        assert_eq!(shots_at(&events), vec![line[1]]);
+        assert!(!attacked(&events));
+
+        // With a clear line, the shot flies up to the mob.
+        world.set_terrain(line[2], Terrain::Ground);
+        let events = world.fire(Dir6::North).unwrap();
+        assert_eq!(shots_at(&events), vec![line[3]]);
+        assert!(attacked(&events));
+
+        // Every shot used up an arrow.
+        assert!(world.find_ammo(player).is_none());
+        assert!(world.fire(Dir6::North).is_err());
+    }
+
+    #[test]
+    fn test_damage_resistance() {
+        let (mut world, player, loc) = new_world();
+        let away = Location::new(loc.x, loc.y + 10, loc.z);
+
+        // The ooze is weak to cold.
+        let ooze = spawn(&mut world, "ooze", away);
+        let hp = world.hp(ooze);
+        world.damage(ooze, 1, Damage::Cold, None);
+        assert_eq!(world.hp(ooze), hp - 2);
+        world.damage(ooze, 1, Damage::Physical, None);
+        assert_eq!(world.hp(ooze), hp - 3);
+
+        // The efreet doesn't mind fire at all.
+        let efreet = spawn(&mut world, "efreet", away);
+        let hp = world.hp(efreet);
+        world.damage(efreet, 10, Damage::Fire, None);
+        assert_eq!(world.hp(efreet), hp);
+
+        // A ring gives its resistance to whoever wears it.
+        assert_eq!(world.resisted_damage(player, 6, Damage::Fire), 6);
+        let ring = give(&mut world, "ring of fire resistance");
+        let slot = slot_of(&world, player, ring);
+        world.equip(slot).unwrap();
+        assert_eq!(world.resisted_damage(player, 6, Damage::Fire), 3);
+        assert_eq!(world.resisted_damage(player, 6, Damage::Cold), 6);
+    }
+
+    #[test]
+    fn test_deathsplosion_chain() {
+        let (mut world, _, loc) = new_world();
+        let line = clear_line(
+            &mut world,
+            Location::new(loc.x, loc.y + 10, loc.z),
+            Dir6::South,
+            4,
+        );
+
+        // Each bomb's blast is strong enough to kill the next one, the last one is out of reach
+        // of the first blast.
+        let mut bombs = Vec::new();
+        for &(power, i) in &[(10, 0), (5, 2), (2, 4)] {
+            let loadout = Loadout::new()
+                .c(Desc::new("bomb", Icon::Ooze))
+                .c(StatsComponent::new(Stats::new(power, &[Intrinsic::Deathsplosion])))
+                .c(Health::default());
+            bombs.push(world.spawn(&loadout, line[i]));
+        }
+
+        world.damage(bombs[0], 100, Damage::Physical, None);
+        assert!(bombs.iter().all(|&e| !world.is_alive(e)));
+
+        // The explosions went off in the order the bombs died.
+        let exploded: Vec<_> = world
+            .next_tick()
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| match e {
+                Event::Explosion { entity, .. } => Some(entity),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(exploded, bombs);
+    }
+
+    #[test]
+    fn test_throw() {
+        let (mut world, player, loc) = new_world();
+        let sword = give(&mut world, "sword");
+        for _ in 0..2 {
+            give(&mut world, "potion of healing");
+        }
+
+        let line = clear_line(&mut world, loc, Dir6::North, 3);
+        let mob = spawn(&mut world, "dreg", line[2]);
+        let hp = world.hp(mob);
+        // Make sure the throw connects.
+        world.ecs_mut().stats[player].base.attack = 100;
+        world.rebuild_stats(player);
+
+        // The sword hits the mob in its way and lands on the floor somewhere.
+        let slot = slot_of(&world, player, sword);
+        world.throw(slot, Dir6::North).unwrap();
+        assert!(!world.is_alive(mob) || world.hp(mob) < hp);
+        assert!(!world.entity_contains(player, sword));
+        assert!(world.items_at(world.location(sword).unwrap()).contains(&sword));
+
+        // Potions shatter one at a time.
+        let stack = world
+            .entities_in(player)
+            .into_iter()
+            .find(|&e| world.spawn_name(e) == Some("potion of healing"))
+            .unwrap();
+        assert_eq!(world.item_count(stack), 2);
+        let slot = slot_of(&world, player, stack);
+        world.throw(slot, Dir6::South).unwrap();
+        assert_eq!(world.item_count(stack), 1);
+        assert!(world.is_identified("potion of healing"));
+    }
+
+    #[test]
+    fn test_level_up() {
+        let (mut world, player, _) = new_world();
+        let power = world.base_stats(player).power;
+        assert_eq!(world.xp_level(player), Some(1));
+
+        world.gain_xp(player, 50);
+        assert_eq!(world.xp_level(player), Some(3));
+        assert!(world.base_stats(player).power > power);
+        assert_eq!(world.stats(player).power, world.base_stats(player).power);
+
+        let levels: Vec<u32> = world
+            .next_tick()
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| match e {
+                Event::LevelUp { level, .. } => Some(level),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(levels, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_hunger() {
+        let (mut world, player, _) = new_world();
+        // Keep natural armor from soaking up starvation damage.
+        world.ecs_mut().health[player].armor = 0;
+
+        for &(satiation, hunger) in &[
+            (Nutrition::MAX, Hunger::Satiated),
+            (500, Hunger::Hungry),
+            (200, Hunger::Weak),
+            (50, Hunger::Fainting),
+            (0, Hunger::Starving),
+        ] {
+            world.ecs_mut().nutrition[player].satiation = satiation;
+            assert_eq!(world.hunger(player), Some(hunger));
+        }
+
+        // Stats drop as soon as the stomach empties past a threshold.
+        world.ecs_mut().nutrition[player].satiation = 201;
+        world.rebuild_stats(player);
+        let attack = world.stats(player).attack;
+        let defense = world.stats(player).defense;
+        assert!(world.consume_nutrition(player));
+        assert_eq!(world.hunger(player), Some(Hunger::Weak));
+        assert_eq!(world.stats(player).attack, attack - Hunger::Weak.penalty());
+        assert_eq!(world.stats(player).defense, defense - Hunger::Weak.penalty());
+
+        // Wounds heal while resting on a full stomach.
+        world.ecs_mut().nutrition[player].satiation = Nutrition::MAX;
+        world.ecs_mut().health[player].wounds = 1;
+        let hp = world.hp(player);
+        world.idle(player);
+        assert!(world.hp(player) > hp);
+
+        // They don't on an empty one, and starving hurts sooner or later.
+        world.ecs_mut().nutrition[player].satiation = 0;
+        world.ecs_mut().health[player].wounds = 1;
+        let hp = world.hp(player);
+        world.idle(player);
+        assert!(world.hp(player) <= hp);
+        assert!(!world.consume_nutrition(player));
+        for _ in 0..100 {
+            if world.hp(player) < hp {
+                break;
+            }
+            world.end_turn(player);
+        }
+        assert!(world.hp(player) < hp);
+
+        // Fainting from hunger makes you lose turns.
+        let delay = world.action_delay(player);
+        let mut fainted = false;
+        for _ in 0..100 {
+            world.ecs_mut().nutrition[player].satiation = 30;
+            world.ecs_mut().status[player].remove(&Status::Delayed);
+            world.end_turn(player);
+            if world.ecs().status[player][&Status::Delayed] > delay {
+                fainted = true;
+                break;
+            }
+        }
+        assert!(fainted);
+    }
+
+    #[test]
+    fn test_eating() {
+        let (mut world, player, loc) = new_world();
+        world.ecs_mut().nutrition[player].satiation = 100;
+
+        let ration = give(&mut world, "ration");
+        let satiation = world.ecs().nutrition[player].satiation;
+        let slot = slot_of(&world, player, ration);
+        world.use_item(slot).unwrap();
+        assert!(!world.entity_contains(player, ration));
+        assert!(world.ecs().nutrition[player].satiation > satiation);
+        assert_eq!(world.hunger(player), Some(Hunger::Satiated));
+
+        // Only food can be eaten.
+        let sword = give(&mut world, "sword");
+        assert!(world.entity_eat(player, sword).is_err());
+
+        // Mobs killed by physical damage leave a corpse to eat, burnt ones don't.
+        let away = Location::new(loc.x, loc.y + 10, loc.z);
+        world.set_terrain(away, Terrain::Ground);
+        let mob = spawn(&mut world, "dreg", away);
+        world.damage(mob, 100, Damage::Physical, None);
+        assert!(!world.is_alive(mob));
+        let corpse = world.item_at(away).unwrap();
+        assert_eq!(world.item_type(corpse), Some(ItemType::Food(200)));
+
+        world.ecs_mut().nutrition[player].satiation = 100;
+        world.entity_eat(player, corpse).unwrap();
+        assert!(!world.is_alive(corpse));
+        assert!(world.ecs().nutrition[player].satiation > 100);
+
+        let mob = spawn(&mut world, "dreg", away);
+        world.damage(mob, 100, Damage::Fire, None);
+        assert!(!world.is_alive(mob));
+        assert!(world.item_at(away).is_none());
+    }
+
+    #[test]
+    fn test_statuses() {
+        let (mut world, _, loc) = new_world();
+        let away = Location::new(loc.x, loc.y + 10, loc.z);
+        world.set_terrain(away, Terrain::Ground);
+        let mob = spawn(&mut world, "dreg", away);
+        world.next_tick().unwrap();
+
+        // Poison hurts once per turn and runs out.
+        let hp = world.hp(mob);
+        world.gain_status(mob, Status::Poisoned, 24);
+        for _ in 0..24 {
+            world.tick_statuses(mob);
+        }
+        assert_eq!(world.hp(mob), hp - 1);
+        assert!(!world.has_status(mob, Status::Poisoned));
+
+        let status_events: Vec<(bool, Status)> = world
+            .next_tick()
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| match e {
+                Event::StatusGained { entity, status } if entity == mob => Some((true, status)),
+                Event::StatusExpired { entity, status } if entity == mob => Some((false, status)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            status_events,
+            vec![(true, Status::Poisoned), (false, Status::Poisoned)]
+        );
+
+        // Regeneration heals wounds.
+        let hp = world.hp(mob);
+        world.gain_status(mob, Status::Regenerating, 24);
+        for _ in 0..12 {
+            world.tick_statuses(mob);
+        }
+        assert!(world.hp(mob) > hp);
+
+        // Sleep lasts until the sleeper gets hurt.
+        world.gain_status(mob, Status::Asleep, 100);
+        world.tick_statuses(mob);
+        assert!(world.has_status(mob, Status::Asleep));
+        world.damage(mob, 1, Damage::Poison, None);
+        assert!(world.is_alive(mob));
+        assert!(!world.has_status(mob, Status::Asleep));
+    }
+
+    #[test]
+    fn test_status_ai() {
+        let (mut world, player, loc) = new_world();
+
+        // Afraid mobs run away from their enemies.
+        let line = clear_line(&mut world, loc, Dir6::North, 4);
+        let mob = spawn(&mut world, "dreg", line[2]);
+        world.designate_enemy(mob, player);
+        world.gain_status(mob, Status::Afraid, 100);
+        world.run_ai_for(mob);
+        assert!(world.location(mob).unwrap().metric_distance(loc) > 2);
+
+        // Sleeping mobs don't notice an invisible player right next to them.
+        let near = loc.jump(&world, Dir6::South);
+        world.set_terrain(near, Terrain::Ground);
+        let sleeper = spawn(&mut world, "dreg", near);
+        world.do_fov(player);
+        world.gain_status(player, Status::Invisible, 100);
+        world.run_ai_for(sleeper);
+        assert_eq!(world.brain_state(sleeper), Some(BrainState::Asleep));
+
+        world.remove_status(player, Status::Invisible);
+        world.run_ai_for(sleeper);
+        assert_eq!(world.brain_state(sleeper), Some(BrainState::Hunting(player)));
+    }
+
+    #[test]
+    fn test_heal_effect() {
+        let (mut world, player, _) = new_world();
+        world.ecs_mut().health[player].wounds = 3;
+        world.apply_effect_to_entity(&Effect::Heal(10), player, None);
+        assert_eq!(world.ecs().health[player].wounds, 0);
+        assert_eq!(world.hp(player), world.max_hp(player));
+    }
+
+    #[test]
+    fn test_magic_map_effect() {
+        let (mut world, player, loc) = new_world();
+        let sector: Vec<Location> = loc.sector().iter().collect();
+        let trap = spawn(&mut world, "sleeping gas trap", *sector.last().unwrap());
+        assert!(world.is_hidden(trap));
+
+        world.apply_effect_to_entity(&Effect::MagicMap, player, None);
+        let memory = &world.ecs().map_memory[player];
+        assert!(sector.iter().all(|loc| memory.remembered.contains(loc)));
+        let elsewhere = Location::new(loc.x, loc.y, loc.z + 1);
+        assert!(!memory.remembered.contains(&elsewhere));
+        assert!(!world.is_hidden(trap));
+    }
+
+    #[test]
+    fn test_command_events() {
+        let (mut world, player, loc) = new_world();
+        let line = clear_line(&mut world, loc, Dir6::North, 3);
+        let door = loc.jump(&world, Dir6::South);
+        world.set_terrain(door, Terrain::Door);
+
+        let events = world.step(Dir6::North).unwrap();
+        assert!(events.iter().any(|e| match *e {
+            Event::Move { entity, from, to } => entity == player && from == loc && to == line[1],
+            _ => false,
+        }));
+
+        world.step(Dir6::South).unwrap();
+        let events = world.step(Dir6::South).unwrap();
+        assert!(events.iter().any(|e| match *e {
+            Event::DoorOpened { entity, loc } => entity == player && loc == door,
+            _ => false,
+        }));
+        assert_eq!(world.terrain(door), Terrain::OpenDoor);
+
+        // Attacks come with a typed event followed by the message text describing it.
+        let mob = spawn(&mut world, "dreg", line[1]);
+        let events = world.melee(Dir6::North).unwrap();
+        let i = events
+            .iter()
+            .position(|e| match *e {
+                Event::Hit { attacker, target, .. } | Event::Miss { attacker, target } => {
+                    attacker == player && target == mob
+                }
+                _ => false,
+            })
+            .unwrap();
+        let verb = match events[i] {
+            Event::Hit { .. } => "hit",
+            _ => "miss",
+        };
+        match events[i + 1] {
+            Event::Msg(ref text) => assert!(text.contains(verb), "{:?}", text),
+            ref e => panic!("Expected message, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_traps() {
+        let (mut world, player, loc) = new_world();
+        let dir = *Dir6::iter()
+            .find(|&&d| world.can_enter(player, loc.jump(&world, d)))
+            .unwrap();
+        let target = loc.jump(&world, dir);
+
+        let trap = spawn(&mut world, "sleeping gas trap", target);
+        assert!(world.is_hidden(trap));
+
+        // Searching finds the trap sooner or later.
+        for _ in 0..100 {
+            if !world.is_hidden(trap) {
+                break;
+            }
+            world.search().unwrap();
+        }
+        assert!(!world.is_hidden(trap));
+
+        let loadout = Loadout::new().c(Desc::new("orb", Icon::Potion)).c(Item {
+            item_type: ItemType::Instant(MagicEffect::Confuse),
+            charges: 1,
+            cursed: false,
+            curse_known: false,
+            count: 1,
+        });
+        let orb = world.spawn(&loadout, target);
+
+        // Stepping on the cell sets off both the instant item and the trap.
+        let events = world.step(dir).unwrap();
+        assert_eq!(world.location(player), Some(target));
+        assert!(!world.is_alive(orb));
+        assert!(!world.is_alive(trap));
+        assert!(world.has_status(player, Status::Confused));
+        assert!(world.has_status(player, Status::Asleep));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| match **e {
+                    Event::Spell { caster: None, .. } => true,
+                    _ => false,
+                })
+                .count(),
+            2
+        );
     }
 }
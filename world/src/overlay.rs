@@ -0,0 +1,45 @@
+use location::{Location, Portal};
+use std::collections::BTreeMap;
+use terrain::Terrain;
+
+/// Changes made to the game world terrain after world generation.
+///
+/// `Worldgen` only saves its seed and is regenerated when a game is loaded, so any terrain or
+/// portal modified during play must be stored here to end up in the save file. Queries should
+/// check the overlay first and fall back to the generated data.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Overlay {
+    terrain: BTreeMap<Location, Terrain>,
+    /// Modified portals. A `None` value means a generated portal has been removed.
+    portals: BTreeMap<Location, Option<Portal>>,
+}
+
+impl Overlay {
+    pub fn new() -> Overlay { Default::default() }
+
+    /// Return overlay terrain if the location has been modified.
+    pub fn terrain(&self, loc: Location) -> Option<Terrain> { self.terrain.get(&loc).cloned() }
+
+    /// Return overlay portal if the location has been modified.
+    ///
+    /// The inner value is `None` if a portal has been removed from the location.
+    pub fn portal(&self, loc: Location) -> Option<Option<Location>> {
+        self.portals.get(&loc).map(|p| p.map(|p| loc + p))
+    }
+
+    pub fn contains_terrain(&self, loc: Location) -> bool { self.terrain.contains_key(&loc) }
+
+    pub fn set_terrain(&mut self, loc: Location, terrain: Terrain) {
+        self.terrain.insert(loc, terrain);
+    }
+
+    pub fn set_portal(&mut self, loc: Location, portal: Option<Portal>) {
+        self.portals.insert(loc, portal);
+    }
+
+    /// Drop overlay terrain, reverting location to generated terrain.
+    pub fn clear_terrain(&mut self, loc: Location) { self.terrain.remove(&loc); }
+
+    /// Drop overlay portal, reverting location to generated portal.
+    pub fn clear_portal(&mut self, loc: Location) { self.portals.remove(&loc); }
+}
@@ -0,0 +1,208 @@
+//! Pathfinding with Dijkstra maps.
+
+use calx::Dir6;
+use location::Location;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use terraform::TerrainQuery;
+
+/// Maximum path cost searched from the goals.
+///
+/// Keeps the maps from expanding over the whole world.
+pub const PATHING_RANGE: u32 = 64;
+
+/// Cost of entering a location with terrain-based movement, or `None` if it can't be entered.
+///
+/// Mobs are not considered, they move around and are checked when the step is taken.
+pub fn terrain_cost<T: TerrainQuery>(w: &T, loc: Location, can_open_doors: bool) -> Option<u32> {
+    let t = w.terrain(loc);
+    if t.is_locked_door() {
+        // Keys are too rare to plan paths around.
+        None
+    } else if t.is_door() {
+        // Doors take a bit longer since they need to be opened.
+        if can_open_doors {
+            Some(2)
+        } else {
+            None
+        }
+    } else if t.blocks_walk() {
+        None
+    } else {
+        Some(1)
+    }
+}
+
+/// Distance field of path costs to the nearest goal location.
+///
+/// Moving downhill in the map from any location will lead to a goal.
+#[derive(Clone, Debug, Default)]
+pub struct DijkstraMap {
+    distances: HashMap<Location, u32>,
+}
+
+impl DijkstraMap {
+    /// Build a map that spreads from goals through locations with a cost.
+    ///
+    /// Neighbors are found with `Location::jump`, so the map follows portals.
+    pub fn new<T, F>(w: &T, goals: &[Location], cost: F, max_cost: u32) -> DijkstraMap
+    where
+        T: TerrainQuery,
+        F: Fn(Location) -> Option<u32>,
+    {
+        let mut distances = HashMap::new();
+        let mut open = BinaryHeap::new();
+
+        for &loc in goals {
+            distances.insert(loc, 0);
+            open.push((Reverse(0), loc));
+        }
+
+        while let Some((Reverse(dist), loc)) = open.pop() {
+            if distances.get(&loc).map_or(false, |&d| d < dist) {
+                // Already found a shorter path here.
+                continue;
+            }
+
+            // Going from the neighbor to this location costs entering this location.
+            let step_cost = match cost(loc) {
+                Some(c) => c,
+                None if dist == 0 => 1,
+                None => continue,
+            };
+            let next_dist = dist + step_cost;
+            if next_dist > max_cost {
+                continue;
+            }
+
+            for &dir in Dir6::iter() {
+                let next = loc.jump(w, dir);
+                if cost(next).is_none() {
+                    continue;
+                }
+                if distances.get(&next).map_or(true, |&d| next_dist < d) {
+                    distances.insert(next, next_dist);
+                    open.push((Reverse(next_dist), next));
+                }
+            }
+        }
+
+        DijkstraMap { distances }
+    }
+
+    /// Return path cost from location to the nearest goal.
+    pub fn distance(&self, loc: Location) -> Option<u32> { self.distances.get(&loc).cloned() }
+
+    /// Return the directions from location that lead closer to a goal, best first.
+    pub fn downhill<T: TerrainQuery>(&self, w: &T, loc: Location) -> Vec<Dir6> {
+        let current = match self.distance(loc) {
+            Some(d) => d,
+            None => return Vec::new(),
+        };
+
+        let mut ret: Vec<(u32, Dir6)> = Dir6::iter()
+            .filter_map(|&dir| {
+                match self.distance(loc.jump(w, dir)) {
+                    Some(d) if d < current => Some((d, dir)),
+                    _ => None,
+                }
+            })
+            .collect();
+        ret.sort_by_key(|&(d, _)| d);
+        ret.into_iter().map(|(_, dir)| dir).collect()
+    }
+}
+
+/// Find the location closest to origin that matches the predicate.
+///
+/// Search spreads through locations with a cost, but the found location itself does not need to
+/// have one.
+pub fn find_nearest<T, F, P>(
+    w: &T,
+    origin: Location,
+    cost: F,
+    predicate: P,
+    max_cost: u32,
+) -> Option<Location>
+where
+    T: TerrainQuery,
+    F: Fn(Location) -> Option<u32>,
+    P: Fn(Location) -> bool,
+{
+    let mut distances = HashMap::new();
+    let mut open = BinaryHeap::new();
+    distances.insert(origin, 0);
+    open.push((Reverse(0), origin));
+
+    while let Some((Reverse(dist), loc)) = open.pop() {
+        if distances.get(&loc).map_or(false, |&d| d < dist) {
+            continue;
+        }
+
+        for &dir in Dir6::iter() {
+            let next = loc.jump(w, dir);
+            if predicate(next) {
+                return Some(next);
+            }
+
+            if let Some(c) = cost(next) {
+                let next_dist = dist + c;
+                if next_dist <= max_cost && distances.get(&next).map_or(true, |&d| next_dist < d) {
+                    distances.insert(next, next_dist);
+                    open.push((Reverse(next_dist), next));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use location::Location;
+    use terraform::TerrainQuery;
+    use terrain::Terrain;
+
+    /// Open field with a wall segment in the way.
+    struct Field;
+
+    impl TerrainQuery for Field {
+        fn is_valid_location(&self, _loc: Location) -> bool { true }
+
+        fn terrain(&self, loc: Location) -> Terrain {
+            if loc.x == 0 && loc.y > -5 && loc.y < 5 {
+                Terrain::Wall
+            } else {
+                Terrain::Ground
+            }
+        }
+
+        fn portal(&self, _loc: Location) -> Option<Location> { None }
+
+        fn is_untouched(&self, _loc: Location) -> bool { false }
+    }
+
+    #[test]
+    fn test_path_around_wall() {
+        let w = Field;
+        let goal = Location::new(1, 0, 0);
+        let map = DijkstraMap::new(&w, &[goal], |loc| terrain_cost(&w, loc, true), 32);
+
+        assert_eq!(map.distance(goal), Some(0));
+        assert_eq!(map.distance(Location::new(0, 0, 0)), None);
+
+        // Walk downhill from the other side of the wall.
+        let mut loc = Location::new(-1, 0, 0);
+        let mut steps = 0;
+        while loc != goal {
+            let dir = map.downhill(&w, loc)[0];
+            loc = loc.jump(&w, dir);
+            assert!(!w.terrain(loc).blocks_walk());
+            steps += 1;
+            assert!(steps < 32);
+        }
+        assert_eq!(map.distance(Location::new(-1, 0, 0)), Some(steps));
+    }
+}
@@ -0,0 +1,82 @@
+//! Experience levels for characters.
+
+use stats::Stats;
+
+/// A step in the experience curve.
+struct Level {
+    /// Total experience needed to reach the level.
+    xp: u32,
+    /// Base power gained on reaching the level.
+    power: i32,
+    /// Base attack gained on reaching the level.
+    attack: i32,
+    /// Base defense gained on reaching the level.
+    defense: i32,
+}
+
+/// The experience curve, starting from level 2. Characters start at level 1.
+#[cfg_attr(rustfmt, rustfmt_skip)]
+static LEVELS: &[Level] = &[
+    Level { xp: 20,   power: 2, attack: 1, defense: 1 },
+    Level { xp: 50,   power: 2, attack: 1, defense: 1 },
+    Level { xp: 100,  power: 2, attack: 1, defense: 1 },
+    Level { xp: 180,  power: 3, attack: 1, defense: 1 },
+    Level { xp: 300,  power: 3, attack: 1, defense: 1 },
+    Level { xp: 460,  power: 3, attack: 2, defense: 1 },
+    Level { xp: 680,  power: 4, attack: 2, defense: 2 },
+    Level { xp: 960,  power: 4, attack: 2, defense: 2 },
+    Level { xp: 1300, power: 5, attack: 2, defense: 2 },
+];
+
+/// Return the level reached with the given total experience.
+pub fn level_for(xp: u32) -> u32 { 1 + LEVELS.iter().take_while(|l| xp >= l.xp).count() as u32 }
+
+/// Return the base stats gained on reaching a level.
+pub fn level_gain(level: u32) -> Stats {
+    if level < 2 {
+        return Default::default();
+    }
+
+    LEVELS
+        .get(level as usize - 2)
+        .map_or_else(Default::default, |l| Stats {
+            power: l.power,
+            attack: l.attack,
+            defense: l.defense,
+            ..Default::default()
+        })
+}
+
+/// Return the experience gained from killing a creature.
+///
+/// Tougher creatures and creatures that only show up deeper in the dungeon are worth more.
+pub fn kill_xp(power: i32, spawn_depth: i32) -> u32 {
+    (power.max(1) * (1 + spawn_depth.max(0))) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::{kill_xp, level_for, level_gain, LEVELS};
+
+    #[test]
+    fn test_experience_curve() {
+        // Levels need more experience as they go up.
+        for w in LEVELS.windows(2) {
+            assert!(w[0].xp < w[1].xp);
+        }
+
+        assert_eq!(level_for(0), 1);
+        assert_eq!(level_for(19), 1);
+        assert_eq!(level_for(20), 2);
+        assert_eq!(level_for(99), 3);
+        assert_eq!(level_for(1_000_000), LEVELS.len() as u32 + 1);
+
+        assert_eq!(level_gain(1).power, 0);
+        assert_eq!(level_gain(2).power, 2);
+        assert_eq!(level_gain(100).power, 0);
+
+        assert_eq!(kill_xp(5, 0), 5);
+        assert_eq!(kill_xp(5, 2), 15);
+        assert_eq!(kill_xp(0, 0), 1);
+    }
+}
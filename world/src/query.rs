@@ -1,17 +1,22 @@
-use calx::{clamp, hex_neighbors, CellVector, Dir6, HexGeom, Noise};
+use calx::{hex_neighbors, CellVector, Dir6, HexGeom, Noise};
 use calx_ecs::Entity;
-use components::{Alignment, BrainState, Icon, Status};
+use components::{Alignment, BrainState, Hunger, Icon, Status};
+use effect::Damage;
 use euclid::vec2;
 use grammar::{Noun, Pronoun};
-use item::{EquipType, ItemType, Slot};
+use item::{Appearance, EquipType, ItemType, MagicEffect, Slot};
+use light::LightMap;
 use location::Location;
 use mapsave;
+use pathing::{self, DijkstraMap, PATHING_RANGE};
+use progression;
 use rand::distributions::Uniform;
-use spec::EntitySpawn;
+use spec::{self, EntitySpawn};
 use stats;
 use stats::Intrinsic;
 use std::collections::{HashSet, VecDeque};
 use std::iter::FromIterator;
+use std::rc::Rc;
 use std::slice;
 use std::str::FromStr;
 use terraform::TerrainQuery;
@@ -37,6 +42,9 @@ pub trait Query: TerrainQuery + Sized {
     /// Return world RNG seed
     fn rng_seed(&self) -> u32;
 
+    /// Return whether the player knows what items with the spawn name are.
+    fn is_identified(&self, spawn_name: &str) -> bool;
+
     /// Return maximum health of an entity.
     fn max_hp(&self, e: Entity) -> i32 { self.stats(e).power }
 
@@ -60,8 +68,26 @@ pub trait Query: TerrainQuery + Sized {
 
     fn entity_contains(&self, parent: Entity, child: Entity) -> bool;
 
+    /// Return the container an entity is stowed in, if any.
+    ///
+    /// Items equipped in inventory slots are not considered to be in a container.
+    fn entity_container(&self, e: Entity) -> Option<Entity>;
+
     fn sphere_volume(&self, origin: Location, radius: u32) -> Volume;
 
+    /// Return the area where a noise made at origin can be heard.
+    fn noise_volume(&self, origin: Location, loudness: u32) -> Volume;
+
+    /// Return a pathing map towards goal.
+    ///
+    /// Maps are cached until the terrain changes or the turn ends.
+    fn path_map(&self, goal: Location, can_open_doors: bool) -> Rc<DijkstraMap>;
+
+    /// Return the light cast by light sources near the player.
+    ///
+    /// The map is cached until the turn ends.
+    fn light_map(&self) -> Rc<LightMap>;
+
     /// Return the AI state of an entity.
     fn brain_state(&self, e: Entity) -> Option<BrainState> {
         self.ecs().brain.get(e).and_then(|brain| Some(brain.state))
@@ -86,6 +112,12 @@ pub trait Query: TerrainQuery + Sized {
         }
     }
 
+    /// Return how much mana the entity has left for casting spells.
+    fn mana(&self, e: Entity) -> i32 {
+        let spent = self.ecs().mana.get(e).map_or(0, |m| m.spent);
+        (self.stats(e).mana - spent).max(0)
+    }
+
     /// Return field of view for a location.
     fn fov_status(&self, loc: Location) -> Option<FovStatus> {
         if let Some(p) = self.player() {
@@ -106,15 +138,27 @@ pub trait Query: TerrainQuery + Sized {
     /// Return visual brush for an entity.
     fn entity_icon(&self, e: Entity) -> Option<Icon> { self.ecs().desc.get(e).map(|x| x.icon) }
 
-    fn entity_name(&self, e: Entity) -> String {
-        self.ecs()
-            .desc
-            .get(e)
-            .map_or_else(|| "N/A".to_string(), |x| x.name.clone())
+    /// Return the display name of an entity, with the count in front for stacks of items.
+    fn entity_name(&self, e: Entity) -> String { self.noun(e).name() }
+
+    /// Return the name of a single entity of this kind.
+    fn singular_name(&self, e: Entity) -> String {
+        let name = self.unidentified_name(e).unwrap_or_else(|| {
+            self.ecs()
+                .desc
+                .get(e)
+                .map_or_else(|| "N/A".to_string(), |x| x.name.clone())
+        });
+
+        if self.is_known_cursed(e) {
+            format!("cursed {}", name)
+        } else {
+            name
+        }
     }
 
     fn noun(&self, e: Entity) -> Noun {
-        let mut ret = Noun::new(self.entity_name(e));
+        let mut ret = Noun::new(self.singular_name(e)).count(self.item_count(e));
         if self.is_player(e) {
             ret = ret.you().pronoun(Pronoun::They);
         }
@@ -151,11 +195,17 @@ pub trait Query: TerrainQuery + Sized {
     fn blocks_sight(&self, loc: Location) -> bool { self.terrain(loc).blocks_sight() }
 
     /// Return whether the entity can occupy a location.
+    ///
+    /// Closed doors count as enterable if the entity is able to open them.
     fn can_enter(&self, e: Entity, loc: Location) -> bool {
-        if self.terrain(loc).is_door() && !self.has_intrinsic(e, Intrinsic::Hands) {
+        let t = self.terrain(loc);
+        if t.is_door() && !self.can_manipulate(e) {
             // Can't open doors without hands.
             return false;
         }
+        if t.is_locked_door() && self.find_key(e).is_none() {
+            return false;
+        }
         if self.blocks_walk(loc) {
             return false;
         }
@@ -205,15 +255,199 @@ pub trait Query: TerrainQuery + Sized {
     }
 
     /// Return first item at given location.
-    fn item_at(&self, loc: Location) -> Option<Entity> {
-        self.entities_at(loc).into_iter().find(|&e| self.is_item(e))
+    fn item_at(&self, loc: Location) -> Option<Entity> { self.items_at(loc).into_iter().next() }
+
+    /// Return all items at given location.
+    ///
+    /// The items are sorted by entity so that the listing stays the same between game sessions.
+    /// Entity indices get reused, so this is not necessarily the order the items were created in.
+    fn items_at(&self, loc: Location) -> Vec<Entity> {
+        let mut ret: Vec<Entity> = self
+            .entities_at(loc)
+            .into_iter()
+            .filter(|&e| self.is_item(e))
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    /// Return the look of an item the player hasn't identified yet.
+    fn unidentified_name(&self, e: Entity) -> Option<String> {
+        let name = self.spawn_name(e)?;
+        if self.is_identified(name) {
+            return None;
+        }
+        spec::unidentified_name(self.rng_seed(), name)
     }
 
+    /// Return whether the item is a potion that breaks when thrown.
+    fn is_potion(&self, e: Entity) -> bool {
+        self.spawn_name(e).and_then(spec::appearance) == Some(Appearance::Potion)
+    }
+
+    /// Return whether the entity has the spell in one of its spell slots.
+    fn knows_spell(&self, e: Entity, effect: MagicEffect) -> bool {
+        Slot::equipped_iter()
+            .filter_map(|&slot| self.entity_equipped(e, slot))
+            .any(|spell| self.item_type(spell) == Some(ItemType::Spell(effect)))
+    }
+
+    /// Return how many items are in the stack, 1 for anything that isn't a stack.
+    fn item_count(&self, e: Entity) -> u32 { self.ecs().item.get(e).map_or(1, |i| i.count) }
+
+    /// Return whether items of this kind can be piled into a single stack.
+    fn is_stackable(&self, e: Entity) -> bool {
+        match self.item_type(e) {
+            Some(ItemType::UntargetedUsable(_))
+            | Some(ItemType::Food(_))
+            | Some(ItemType::Ammo) => true,
+            _ => false,
+        }
+    }
+
+    /// Return whether the two items can be merged into one stack.
+    fn stacks_with(&self, a: Entity, b: Entity) -> bool {
+        if a == b || !self.is_stackable(a) {
+            return false;
+        }
+        match (self.ecs().item.get(a), self.ecs().item.get(b)) {
+            (Some(x), Some(y)) => {
+                x.item_type == y.item_type
+                    && x.cursed == y.cursed
+                    && x.curse_known == y.curse_known
+                    && self.spawn_name(a) == self.spawn_name(b)
+            }
+            _ => false,
+        }
+    }
+
+    /// Return a bag stack carried by the entity that the item can be merged into.
+    fn matching_stack(&self, e: Entity, item: Entity) -> Option<Entity> {
+        Slot::iter()
+            .filter(|x| !x.is_equipment_slot())
+            .filter_map(|&x| self.entity_equipped(e, x))
+            .find(|&x| self.stacks_with(x, item))
+    }
+
+    /// Return how many items fit in a container, `None` if the entity isn't a container.
+    fn capacity(&self, e: Entity) -> Option<u32> {
+        self.ecs().container.get(e).map(|c| c.capacity)
+    }
+
+    /// Return the items stowed in a container in a stable order.
+    fn contents(&self, container: Entity) -> Vec<Entity> {
+        let mut ret: Vec<Entity> = self
+            .entities_in(container)
+            .into_iter()
+            .filter(|&e| self.entity_container(e) == Some(container))
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    /// Return containers the entity can put things in or take things out of.
+    ///
+    /// These are the containers lying where the entity stands followed by the ones it carries.
+    fn reachable_containers(&self, e: Entity) -> Vec<Entity> {
+        let mut ret: Vec<Entity> = self.location(e).map_or_else(Vec::new, |loc| {
+            self.entities_at(loc)
+                .into_iter()
+                .filter(|&c| self.capacity(c).is_some())
+                .collect()
+        });
+        ret.sort();
+        let mut carried: Vec<Entity> = self
+            .entities_in(e)
+            .into_iter()
+            .filter(|&c| self.capacity(c).is_some())
+            .collect();
+        carried.sort();
+        ret.extend(carried);
+        ret
+    }
+
+    /// Return items the entity can pick up.
+    ///
+    /// These are the items lying where the entity stands followed by the contents of the
+    /// containers it can reach, including the ones it carries.
+    fn reachable_items(&self, e: Entity) -> Vec<Entity> {
+        let mut ret = self.location(e).map_or_else(Vec::new, |loc| self.items_at(loc));
+        for c in self.reachable_containers(e) {
+            ret.extend(self.contents(c));
+        }
+        ret
+    }
+
+    /// Return whether the item is cursed and the curse has been found out.
+    fn is_known_cursed(&self, e: Entity) -> bool {
+        self.ecs()
+            .item
+            .get(e)
+            .map_or(false, |i| i.cursed && i.curse_known)
+    }
+
+    /// Return a key item carried by the entity.
+    fn find_key(&self, e: Entity) -> Option<Entity> {
+        self.entities_in(e)
+            .into_iter()
+            .find(|&item| self.item_type(item) == Some(ItemType::Key))
+    }
+
+    /// Return the ammunition the entity is carrying for its ranged weapon.
+    fn find_ammo(&self, e: Entity) -> Option<Entity> {
+        self.entities_in(e)
+            .into_iter()
+            .find(|&item| self.item_type(item) == Some(ItemType::Ammo))
+    }
+
+    /// Return whether the entity can open doors and handle items.
+    fn can_manipulate(&self, e: Entity) -> bool { self.has_intrinsic(e, Intrinsic::Hands) }
+
     /// Return whether the entity has a specific intrinsic property (eg. poison resistance).
     fn has_intrinsic(&self, e: Entity, intrinsic: Intrinsic) -> bool {
         self.stats(e).intrinsics & (1 << intrinsic as u32) != 0
     }
 
+    /// Scale damage of a type by the entity's resistances and vulnerabilities.
+    ///
+    /// Resistance halves and vulnerability doubles the damage, immunity negates it.
+    fn resisted_damage(&self, e: Entity, amount: i32, damage: Damage) -> i32 {
+        if let Some((immunity, resistance, vulnerability)) = damage.resistances() {
+            if self.has_intrinsic(e, immunity) {
+                return 0;
+            }
+            match (
+                self.has_intrinsic(e, resistance),
+                self.has_intrinsic(e, vulnerability),
+            ) {
+                (true, false) => amount / 2,
+                (false, true) => amount * 2,
+                _ => amount,
+            }
+        } else {
+            amount
+        }
+    }
+
+    /// Return whether the entity should not be shown to the player.
+    fn is_hidden(&self, e: Entity) -> bool {
+        if self.ecs().trap.get(e).map_or(false, |t| t.hidden) {
+            return true;
+        }
+        !self.is_player(e) && self.has_status(e, Status::Invisible)
+    }
+
+    /// Return whether the entity is a trap set in the floor.
+    fn is_trap(&self, e: Entity) -> bool { self.ecs().trap.contains(e) }
+
+    /// Return the magic an instant item or a trap sets off when stepped on.
+    fn instant_effect(&self, e: Entity) -> Option<MagicEffect> {
+        if let Some(ItemType::Instant(effect)) = self.item_type(e) {
+            return Some(effect);
+        }
+        self.ecs().trap.get(e).map(|t| t.effect)
+    }
+
     /// Return whether the entity has a specific temporary status
     fn has_status(&self, e: Entity, status: Status) -> bool {
         self.ecs()
@@ -297,7 +531,7 @@ pub trait Query: TerrainQuery + Sized {
 
     /// Return whether the entity is a mob that will act this frame.
     fn acts_this_frame(&self, e: Entity) -> bool {
-        if !self.is_active(e) {
+        if !self.is_active(e) || self.has_status(e, Status::Asleep) {
             return false;
         }
         self.ticks_this_frame(e)
@@ -329,13 +563,41 @@ pub trait Query: TerrainQuery + Sized {
         None
     }
 
+    /// Return the step that takes the entity farthest away from a threat.
+    ///
+    /// Returns `None` if no step increases the distance.
+    fn flee_dir(&self, e: Entity, threat: Location) -> Option<Dir6> {
+        let origin = self.location(e)?;
+        let mut best = None;
+        let mut best_dist = origin.metric_distance(threat);
+        for &dir in Dir6::iter() {
+            let loc = origin.jump(self, dir);
+            if !self.can_enter(e, loc) {
+                continue;
+            }
+            let dist = loc.metric_distance(threat);
+            if dist > best_dist {
+                best_dist = dist;
+                best = Some(dir);
+            }
+        }
+        best
+    }
+
     /// Try to get the next step on the path from origin towards destination.
     ///
-    /// Tries to be fast, not necessarily doing proper pathfinding.
+    /// Follows a cached Dijkstra map, and falls back to heading in the general direction of the
+    /// destination if it is out of pathing range.
     fn pathing_dir_towards(&self, e: Entity, destination: Location) -> Option<Dir6> {
-        // Could do all sorts of cool things here eventually like a Dijkstra map cache, but for now
-        // just doing very simple stuff.
         if let Some(origin) = self.location(e) {
+            let map = self.path_map(destination, self.can_manipulate(e));
+            if map.distance(origin).is_some() {
+                return map
+                    .downhill(self, origin)
+                    .into_iter()
+                    .find(|&dir| self.can_enter(e, origin.jump(self, dir)));
+            }
+
             if let Some(dir) = origin.dir6_towards(destination) {
                 // Try direct approach, the the other directions.
                 for &turn in &[0, 1, -1, 2, -2, 3] {
@@ -351,6 +613,34 @@ pub trait Query: TerrainQuery + Sized {
         None
     }
 
+    /// Return the next step towards the nearest location the entity hasn't seen yet.
+    ///
+    /// Only paths through locations in the entity's map memory.
+    fn explore_dir(&self, e: Entity) -> Option<Dir6> {
+        let origin = self.location(e)?;
+        let memory = self.ecs().map_memory.get(e)?;
+        let can_open_doors = self.can_manipulate(e);
+        let known_cost = |loc: Location| {
+            if memory.remembered.contains(&loc) {
+                pathing::terrain_cost(self, loc, can_open_doors)
+            } else {
+                None
+            }
+        };
+
+        let target = pathing::find_nearest(
+            self,
+            origin,
+            &known_cost,
+            |loc| !memory.remembered.contains(&loc),
+            PATHING_RANGE,
+        )?;
+        let map = DijkstraMap::new(self, &[target], &known_cost, PATHING_RANGE);
+        map.downhill(self, origin)
+            .into_iter()
+            .find(|&dir| self.can_enter(e, origin.jump(self, dir)))
+    }
+
     /// Return whether the entity wants to fight the other entity.
     fn is_hostile_to(&self, e: Entity, other: Entity) -> bool {
         let (a, b) = (self.alignment(e), self.alignment(other));
@@ -545,6 +835,20 @@ pub trait Query: TerrainQuery + Sized {
         })
     }
 
+    /// Return the experience level of an entity that can gain levels.
+    fn xp_level(&self, e: Entity) -> Option<u32> { self.ecs().experience.get(e).map(|x| x.level) }
+
+    /// Return how much experience killing the entity is worth.
+    fn kill_xp(&self, e: Entity) -> u32 {
+        let depth = self.spawn_name(e).and_then(spec::spawn_depth).unwrap_or(0);
+        progression::kill_xp(self.base_stats(e).power, depth)
+    }
+
+    /// Return how hungry the entity is, if it needs to eat.
+    fn hunger(&self, e: Entity) -> Option<Hunger> {
+        self.ecs().nutrition.get(e).map(|n| n.hunger())
+    }
+
     fn equip_type(&self, item: Entity) -> Option<EquipType> {
         use ItemType::*;
         match self.item_type(item) {
@@ -553,7 +857,7 @@ pub trait Query: TerrainQuery + Sized {
             Some(Helmet) => Some(EquipType::Head),
             Some(Armor) => Some(EquipType::Body),
             Some(Boots) => Some(EquipType::Feet),
-            Some(Spell) => Some(EquipType::Spell),
+            Some(Spell(_)) => Some(EquipType::Spell),
             Some(Trinket) => Some(EquipType::Trinket),
             _ => None,
         }
@@ -561,26 +865,16 @@ pub trait Query: TerrainQuery + Sized {
 
     fn is_underground(&self, loc: Location) -> bool { loc.z > 0 }
 
+    /// Return how brightly a location is lit, from 0.0 to 1.0.
     fn light_level(&self, loc: Location) -> f32 {
-        // Lit terrain is lit.
-        if self.terrain(loc).is_luminous() {
-            return 1.0;
-        }
+        // Places away from light sources aren't completely pitch black.
+        const DARK_AMBIENT: f32 = 0.25;
 
-        // In dark arears, far-away things are dim.
-        if self.is_underground(loc) {
-            if let Some(player) = self.player() {
-                if let Some(player_loc) = self.location(player) {
-                    // XXX: This is going to get so messed up with portals, should be done in
-                    // player chart space, not here...
-                    if let Some(dist) = player_loc.distance_from(loc) {
-                        return clamp(0.0, 1.0, 1.0 - (dist as f32 / 8.0));
-                    }
-                }
-            }
+        // Above ground things are bright.
+        if !self.is_underground(loc) {
+            return 1.0;
         }
 
-        // Otherwise things are bright.
-        1.0
+        self.light_map().get(loc).max(DARK_AMBIENT)
     }
 }
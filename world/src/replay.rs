@@ -0,0 +1,345 @@
+//! Recording player commands and replaying them.
+//!
+//! The world is fully determined by its seed and the sequence of commands fed to it, so a
+//! recorded command log can rebuild any game session. State hashes are stored along the log at
+//! regular intervals to detect where a replay diverges from the original run.
+
+use calx::Dir6;
+use calx_ecs::Entity;
+use command::{Command, CommandResult};
+use item::Slot;
+use location::Location;
+use mutate::Mutate;
+use ron;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::ops::{Deref, DerefMut};
+use world::World;
+
+/// Store a state hash after every this many log entries.
+const CHECKPOINT_INTERVAL: usize = 32;
+
+/// Serializable form of a `Command` call.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RecordedCommand {
+    Step(Dir6),
+    Melee(Dir6),
+    Fire(Dir6),
+    Explore,
+    Travel(Location),
+    Open(Dir6),
+    Close(Dir6),
+    Pass,
+    Search,
+    Take(Entity),
+    Drop(Slot),
+    DropSome(Slot, u32),
+    PutIn(Slot, Entity),
+    TakeOut(Entity),
+    Equip(Slot),
+    UseItem(Slot),
+    Zap(Slot, Dir6),
+    Throw(Slot, Dir6),
+    Cast(Slot, Option<Dir6>),
+    /// Advance the world this many ticks while the player can't act.
+    Wait(u32),
+}
+
+impl RecordedCommand {
+    /// Run the command on a world.
+    pub fn apply(self, world: &mut World) -> CommandResult {
+        use self::RecordedCommand::*;
+        match self {
+            Step(dir) => world.step(dir),
+            Melee(dir) => world.melee(dir),
+            Fire(dir) => world.fire(dir),
+            Explore => world.explore(),
+            Travel(loc) => world.travel(loc),
+            Open(dir) => world.open(dir),
+            Close(dir) => world.close(dir),
+            Pass => world.pass(),
+            Search => world.search(),
+            Take(item) => world.take(item),
+            Drop(slot) => world.drop(slot),
+            DropSome(slot, count) => world.drop_some(slot, count),
+            PutIn(slot, container) => world.put_in(slot, container),
+            TakeOut(item) => world.take_out(item),
+            Equip(slot) => world.equip(slot),
+            UseItem(slot) => world.use_item(slot),
+            Zap(slot, dir) => world.zap_item(slot, dir),
+            Throw(slot, dir) => world.throw(slot, dir),
+            Cast(slot, dir) => world.cast(slot, dir),
+            Wait(n) => {
+                let mut events = Vec::new();
+                for _ in 0..n {
+                    events.extend(world.next_tick()?);
+                }
+                Ok(events)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub command: RecordedCommand,
+    /// World state hash after the command, only present at checkpoints.
+    pub checksum: Option<u64>,
+}
+
+/// A recorded game session.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u32,
+    pub log: Vec<Entry>,
+}
+
+impl Replay {
+    pub fn new(seed: u32) -> Replay { Replay { seed, log: Vec::new() } }
+
+    pub fn load<R: Read>(reader: &mut R) -> Result<Replay, Box<Error>> {
+        Ok(ron::de::from_reader(reader)?)
+    }
+
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<(), Box<Error>> {
+        let enc = ron::ser::to_string_pretty(self, Default::default())?;
+        writeln!(writer, "{}", enc)?;
+        Ok(())
+    }
+
+    /// Rebuild the game session from the seed and the command log.
+    pub fn run(&self) -> Result<World, ReplayError> {
+        let mut world = World::new(self.seed);
+        for (i, entry) in self.log.iter().enumerate() {
+            if entry.command.apply(&mut world).is_err() {
+                return Err(ReplayError::CommandFailed(i));
+            }
+
+            if let Some(checksum) = entry.checksum {
+                if world.state_hash() != checksum {
+                    return Err(ReplayError::Desync(i));
+                }
+            }
+        }
+        Ok(world)
+    }
+
+    fn push(&mut self, command: RecordedCommand, world: &World) {
+        // Merge consecutive waits unless the previous one is a checkpoint.
+        if let RecordedCommand::Wait(n) = command {
+            if let Some(&mut Entry {
+                command: RecordedCommand::Wait(ref mut m),
+                checksum: None,
+            }) = self.log.last_mut()
+            {
+                *m += n;
+                return;
+            }
+        }
+
+        let checksum = if (self.log.len() + 1) % CHECKPOINT_INTERVAL == 0 {
+            Some(world.state_hash())
+        } else {
+            None
+        };
+        self.log.push(Entry { command, checksum });
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// Command at log index could not be executed.
+    CommandFailed(usize),
+    /// World state differed from the recorded one after log index.
+    Desync(usize),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::CommandFailed(i) => write!(f, "Replay command {} failed", i),
+            ReplayError::Desync(i) => write!(f, "Replay diverged at command {}", i),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+/// World wrapper that records the commands run on it.
+///
+/// Reading from and directly mutating the world go through `Deref`. Only the command methods
+/// implemented on `Recorder` itself are recorded, anything else that changes the world will make
+/// the recording unreplayable.
+pub struct Recorder {
+    world: World,
+    replay: Option<Replay>,
+}
+
+impl Recorder {
+    /// Start recording a new game.
+    pub fn new(seed: u32) -> Recorder {
+        Recorder {
+            world: World::new(seed),
+            replay: Some(Replay::new(seed)),
+        }
+    }
+
+    /// Wrap a world that has no known history, eg. one loaded from a save file.
+    ///
+    /// No replay will be recorded.
+    pub fn from_world(world: World) -> Recorder {
+        Recorder {
+            world,
+            replay: None,
+        }
+    }
+
+    /// Run a replay and keep recording from where it ends.
+    pub fn from_replay(replay: Replay) -> Result<Recorder, ReplayError> {
+        let world = replay.run()?;
+        Ok(Recorder {
+            world,
+            replay: Some(replay),
+        })
+    }
+
+    /// Return the recorded session if the world history is known.
+    pub fn replay(&self) -> Option<&Replay> { self.replay.as_ref() }
+
+    fn record(&mut self, command: RecordedCommand) -> CommandResult {
+        let ret = command.apply(&mut self.world)?;
+        if let Some(ref mut replay) = self.replay {
+            replay.push(command, &self.world);
+        }
+        Ok(ret)
+    }
+
+    pub fn step(&mut self, dir: Dir6) -> CommandResult { self.record(RecordedCommand::Step(dir)) }
+
+    pub fn melee(&mut self, dir: Dir6) -> CommandResult {
+        self.record(RecordedCommand::Melee(dir))
+    }
+
+    pub fn fire(&mut self, dir: Dir6) -> CommandResult { self.record(RecordedCommand::Fire(dir)) }
+
+    pub fn explore(&mut self) -> CommandResult { self.record(RecordedCommand::Explore) }
+
+    pub fn travel(&mut self, destination: Location) -> CommandResult {
+        self.record(RecordedCommand::Travel(destination))
+    }
+
+    pub fn open(&mut self, dir: Dir6) -> CommandResult { self.record(RecordedCommand::Open(dir)) }
+
+    pub fn close(&mut self, dir: Dir6) -> CommandResult {
+        self.record(RecordedCommand::Close(dir))
+    }
+
+    pub fn pass(&mut self) -> CommandResult { self.record(RecordedCommand::Pass) }
+
+    pub fn search(&mut self) -> CommandResult { self.record(RecordedCommand::Search) }
+
+    pub fn take(&mut self, item: Entity) -> CommandResult {
+        self.record(RecordedCommand::Take(item))
+    }
+
+    pub fn drop(&mut self, slot: Slot) -> CommandResult { self.record(RecordedCommand::Drop(slot)) }
+
+    pub fn drop_some(&mut self, slot: Slot, count: u32) -> CommandResult {
+        self.record(RecordedCommand::DropSome(slot, count))
+    }
+
+    pub fn put_in(&mut self, slot: Slot, container: Entity) -> CommandResult {
+        self.record(RecordedCommand::PutIn(slot, container))
+    }
+
+    pub fn take_out(&mut self, item: Entity) -> CommandResult {
+        self.record(RecordedCommand::TakeOut(item))
+    }
+
+    pub fn equip(&mut self, slot: Slot) -> CommandResult {
+        self.record(RecordedCommand::Equip(slot))
+    }
+
+    pub fn use_item(&mut self, slot: Slot) -> CommandResult {
+        self.record(RecordedCommand::UseItem(slot))
+    }
+
+    pub fn zap_item(&mut self, slot: Slot, dir: Dir6) -> CommandResult {
+        self.record(RecordedCommand::Zap(slot, dir))
+    }
+
+    pub fn throw(&mut self, slot: Slot, dir: Dir6) -> CommandResult {
+        self.record(RecordedCommand::Throw(slot, dir))
+    }
+
+    pub fn cast(&mut self, slot: Slot, dir: Option<Dir6>) -> CommandResult {
+        self.record(RecordedCommand::Cast(slot, dir))
+    }
+
+    /// Advance the world while the player is not acting.
+    pub fn next_tick(&mut self) -> CommandResult { self.record(RecordedCommand::Wait(1)) }
+}
+
+impl Deref for Recorder {
+    type Target = World;
+
+    fn deref(&self) -> &World { &self.world }
+}
+
+impl DerefMut for Recorder {
+    fn deref_mut(&mut self) -> &mut World { &mut self.world }
+}
+
+/// FNV-1a hasher for replay state hashes.
+///
+/// The standard library's `DefaultHasher` may change its algorithm between Rust releases, which
+/// would invalidate the checksums in every recorded replay.
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> StateHasher { StateHasher(0xcbf2_9ce4_8422_2325) }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 { self.0 }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let mut recorder = Recorder::new(1);
+        for i in 0..100 {
+            let dir = *Dir6::iter().nth(i * 7 % 6).unwrap();
+            let _ = recorder.step(dir);
+            if i % 5 == 0 {
+                let _ = recorder.melee(dir);
+            }
+            let _ = recorder.next_tick();
+        }
+
+        let replay = recorder.replay().unwrap().clone();
+        assert!(replay.log.iter().any(|e| e.checksum.is_some()));
+
+        let world = replay.run().unwrap();
+        assert_eq!(world.state_hash(), recorder.state_hash());
+    }
+
+    #[test]
+    fn test_state_hasher() {
+        let mut hasher = StateHasher::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -1,5 +1,9 @@
-use components::{Anim, Brain, Desc, Health, Icon, Item, ShoutType, StatsComponent, Statuses};
-use item::ItemType;
+use components::{
+    Anim, Brain, Container, Desc, Health, Icon, Item, ShoutType, StatsComponent, Statuses, Trap,
+};
+use calx::seeded_rng;
+use item::{Appearance, ItemType, MagicEffect};
+use rand::Rng as _Rng;
 use serde;
 use stats::{Intrinsic, Stats};
 use std::collections::HashMap;
@@ -23,6 +27,12 @@ pub trait Spec: Distribution<Loadout> + Sync + Send {
     fn min_depth(&self) -> i32;
 
     fn name(&self) -> &str;
+
+    /// Shared look of the spec's kind while it's unidentified, if it has one.
+    fn appearance(&self) -> Option<Appearance> { None }
+
+    /// Whether the spec makes an item that can be found as treasure.
+    fn is_item(&self) -> bool { false }
 }
 
 #[derive(Debug)]
@@ -32,8 +42,14 @@ pub struct MobSpec {
     depth: i32,
     rarity: f32,
     power: i32,
+    /// Size of the mana pool for casting spells.
+    mana: i32,
+    /// Radius of light the creature glows with.
+    light: u32,
     intrinsics: Vec<Intrinsic>,
     shout: ShoutType,
+    ranged_range: u32,
+    ranged_power: i32,
 }
 
 impl Default for MobSpec {
@@ -44,8 +60,12 @@ impl Default for MobSpec {
             depth: 0,
             rarity: 1.0,
             power: 0,
+            mana: 0,
+            light: 0,
             intrinsics: Vec::new(),
             shout: ShoutType::Silent,
+            ranged_range: 0,
+            ranged_power: 0,
         }
     }
 }
@@ -53,12 +73,18 @@ impl Default for MobSpec {
 impl Distribution<Loadout> for MobSpec {
     fn sample(&self, _: &mut Rng) -> Loadout {
         Loadout::new()
-            .c(StatsComponent::new(Stats::new(
-                self.power,
-                &self.intrinsics,
-            )))
+            .c(StatsComponent::new(
+                Stats::new(self.power, &self.intrinsics)
+                    .mana(self.mana)
+                    .light(self.light)
+                    .ranged_range(self.ranged_range)
+                    .ranged_power(self.ranged_power),
+            ))
             .c(Desc::new(&self.name, self.icon))
-            .c(Brain::enemy())
+            .c(Brain {
+                shout: self.shout,
+                ..Brain::enemy()
+            })
             .c(Anim::default())
             .c(Health::default())
             .c(Statuses::default())
@@ -82,7 +108,17 @@ pub struct ItemSpec {
     armor: i32,
     attack: i32,
     defense: i32,
+    ranged_range: u32,
+    ranged_power: i32,
+    /// Radius of light shed by the item, both lying on the floor and when equipped.
+    light: u32,
     intrinsics: Vec<Intrinsic>,
+    /// Probability of the item being generated cursed.
+    curse_chance: f32,
+    /// How many items fit inside, nonzero makes the item a container.
+    capacity: u32,
+    /// Shared look of the item kind until it is identified.
+    appearance: Option<Appearance>,
 }
 
 impl Default for ItemSpec {
@@ -97,25 +133,47 @@ impl Default for ItemSpec {
             armor: 0,
             attack: 0,
             defense: 0,
+            ranged_range: 0,
+            ranged_power: 0,
+            light: 0,
             intrinsics: Vec::new(),
+            curse_chance: 0.0,
+            capacity: 0,
+            appearance: None,
         }
     }
 }
 
 impl Distribution<Loadout> for ItemSpec {
-    fn sample(&self, _: &mut Rng) -> Loadout {
-        Loadout::new()
+    fn sample(&self, rng: &mut Rng) -> Loadout {
+        let cursed = self.curse_chance > 0.0 && rng.gen::<f32>() < self.curse_chance;
+
+        let loadout = Loadout::new()
             .c(Desc::new(&self.name, self.icon))
             .c(StatsComponent::new(
                 Stats::new(self.power, &self.intrinsics)
                     .armor(self.armor)
                     .attack(self.attack)
-                    .defense(self.defense),
+                    .defense(self.defense)
+                    .ranged_range(self.ranged_range)
+                    .ranged_power(self.ranged_power)
+                    .light(self.light),
             ))
             .c(Item {
                 item_type: self.item_type,
                 charges: 1,
+                cursed,
+                curse_known: false,
+                count: 1,
+            });
+
+        if self.capacity > 0 {
+            loadout.c(Container {
+                capacity: self.capacity,
             })
+        } else {
+            loadout
+        }
     }
 }
 
@@ -123,6 +181,59 @@ impl Spec for ItemSpec {
     fn rarity(&self) -> f32 { self.rarity }
     fn min_depth(&self) -> i32 { self.depth }
     fn name(&self) -> &str { &self.name }
+    fn is_item(&self) -> bool { true }
+
+    fn appearance(&self) -> Option<Appearance> { self.appearance }
+}
+
+/// Furniture that holds items, like chests.
+///
+/// Containers are only placed by map generation and can't be picked up.
+#[derive(Debug)]
+pub struct ContainerSpec {
+    name: String,
+    icon: Icon,
+    capacity: u32,
+}
+
+impl Distribution<Loadout> for ContainerSpec {
+    fn sample(&self, _: &mut Rng) -> Loadout {
+        Loadout::new()
+            .c(Desc::new(&self.name, self.icon))
+            .c(Container {
+                capacity: self.capacity,
+            })
+    }
+}
+
+impl Spec for ContainerSpec {
+    fn rarity(&self) -> f32 { 0.0 }
+    fn min_depth(&self) -> i32 { 0 }
+    fn name(&self) -> &str { &self.name }
+}
+
+/// Hidden floor trap, placed by map generation.
+#[derive(Debug)]
+pub struct TrapSpec {
+    name: String,
+    effect: MagicEffect,
+}
+
+impl Distribution<Loadout> for TrapSpec {
+    fn sample(&self, _: &mut Rng) -> Loadout {
+        Loadout::new()
+            .c(Desc::new(&self.name, Icon::Trap))
+            .c(Trap {
+                effect: self.effect,
+                hidden: true,
+            })
+    }
+}
+
+impl Spec for TrapSpec {
+    fn rarity(&self) -> f32 { 0.0 }
+    fn min_depth(&self) -> i32 { 0 }
+    fn name(&self) -> &str { &self.name }
 }
 
 macro_rules! specs {
@@ -141,6 +252,40 @@ macro_rules! specs {
 
 pub fn iter_specs() -> impl Iterator<Item = Arc<dyn Spec + 'static>> { SPECS.values().cloned() }
 
+/// Return the smallest depth where the named spec spawns.
+pub fn spawn_depth(name: &str) -> Option<i32> {
+    SPECS
+        .get(&EntitySpawn(name.to_string()))
+        .map(|s| s.min_depth())
+}
+
+/// Return the shared look of the named item kind while it's unidentified.
+pub fn appearance(name: &str) -> Option<Appearance> {
+    SPECS.get(&EntitySpawn(name.to_string()))?.appearance()
+}
+
+/// Return what an unidentified item kind looks like in the game with the given seed.
+///
+/// Kinds that share an appearance get a different shuffle of its labels in every game. Returns
+/// `None` for kinds that are always recognized.
+pub fn unidentified_name(seed: u32, name: &str) -> Option<String> {
+    let appearance = self::appearance(name)?;
+
+    let mut kinds: Vec<&str> = SPECS
+        .values()
+        .filter(|s| s.appearance() == Some(appearance))
+        .map(|s| s.name())
+        .collect();
+    kinds.sort();
+    let idx = kinds.iter().position(|&n| n == name)?;
+
+    let mut labels = appearance.labels().to_vec();
+    let mut rng: Rng = seeded_rng(&(seed, appearance));
+    rng.shuffle(&mut labels);
+    debug_assert!(kinds.len() <= labels.len(), "Out of labels for {:?}", appearance);
+    Some(appearance.describe(labels[idx % labels.len()]))
+}
+
 use self::Intrinsic::*;
 use self::ShoutType::*;
 use item::MagicEffect::*;
@@ -154,6 +299,7 @@ specs!{
         icon: I::Player,
         rarity: 0.0,
         power: 10,
+        mana: PLAYER_MANA,
         intrinsics: vec![Hands],
         shout: Shout,
         ..d()
@@ -171,6 +317,7 @@ specs!{
         icon: I::Snake,
         power: 1,
         shout: Hiss,
+        intrinsics: vec![Poisonous],
         ..d()
     },
     MobSpec {
@@ -178,6 +325,7 @@ specs!{
         icon: I::Ooze,
         depth: 1,
         power: 3,
+        intrinsics: vec![ColdVulnerability],
         shout: Gurgle,
         ..d()
     },
@@ -205,6 +353,9 @@ specs!{
         power: 7,
         intrinsics: vec![Hands],
         shout: Shout,
+        // Throws rocks.
+        ranged_range: 4,
+        ranged_power: 6,
         ..d()
     },
     MobSpec {
@@ -222,7 +373,8 @@ specs!{
         depth: 7,
         rarity: 8.0,
         power: 14,
-        intrinsics: vec![Hands],
+        light: 3,
+        intrinsics: vec![Hands, FireImmunity, Deathsplosion],
         ..d()
     },
     MobSpec {
@@ -243,6 +395,7 @@ specs!{
         item_type: MeleeWeapon,
         rarity: 10.0,
         attack: 6,
+        curse_chance: 0.1,
         ..d()
     },
     ItemSpec {
@@ -251,6 +404,7 @@ specs!{
         item_type: Helmet,
         rarity: 10.0,
         armor: 2,
+        curse_chance: 0.1,
         ..d()
     },
     ItemSpec {
@@ -259,11 +413,60 @@ specs!{
         item_type: Armor,
         rarity: 10.0,
         armor: 5,
+        curse_chance: 0.1,
+        ..d()
+    },
+    ItemSpec {
+        name: "bow".into(),
+        icon: I::Bow,
+        item_type: RangedWeapon,
+        rarity: 10.0,
+        depth: 1,
+        ranged_range: 6,
+        ranged_power: 5,
+        curse_chance: 0.1,
+        ..d()
+    },
+    ItemSpec {
+        name: "arrow".into(),
+        icon: I::Arrow,
+        item_type: Ammo,
+        rarity: 5.0,
+        depth: 1,
+        ..d()
+    },
+    ItemSpec {
+        name: "ring of fire resistance".into(),
+        icon: I::Ring,
+        item_type: Trinket,
+        rarity: 20.0,
+        depth: 2,
+        intrinsics: vec![FireResistance],
+        curse_chance: 0.1,
+        ..d()
+    },
+    ItemSpec {
+        name: "ring of cold resistance".into(),
+        icon: I::Ring,
+        item_type: Trinket,
+        rarity: 20.0,
+        depth: 2,
+        intrinsics: vec![ColdResistance],
+        curse_chance: 0.1,
+        ..d()
+    },
+    ItemSpec {
+        name: "torch".into(),
+        icon: I::Torch,
+        item_type: Trinket,
+        rarity: 10.0,
+        light: 5,
         ..d()
     },
     ItemSpec {
         name: "wand of fireball".into(),
         icon: I::Wand1,
+        appearance: Some(Appearance::Wand),
         power: 5,
         item_type: TargetedUsable(Fireball),
         rarity: 10.0,
@@ -273,19 +476,157 @@ specs!{
     ItemSpec {
         name: "wand of confusion".into(),
         icon: I::Wand2,
+        appearance: Some(Appearance::Wand),
         power: 5,
         item_type: TargetedUsable(Confuse),
         rarity: 10.0,
         armor: 5,
         ..d()
     },
+    ItemSpec {
+        name: "wand of sleep".into(),
+        icon: I::Wand3,
+        appearance: Some(Appearance::Wand),
+        power: 5,
+        item_type: TargetedUsable(Sleep),
+        rarity: 10.0,
+        depth: 1,
+        ..d()
+    },
+    ItemSpec {
+        name: "wand of fear".into(),
+        icon: I::Wand3,
+        appearance: Some(Appearance::Wand),
+        power: 5,
+        item_type: TargetedUsable(Fear),
+        rarity: 10.0,
+        depth: 2,
+        ..d()
+    },
+    ItemSpec {
+        name: "key".into(),
+        icon: I::Key,
+        item_type: Key,
+        // Only placed by map generation.
+        rarity: 0.0,
+        ..d()
+    },
+    ItemSpec {
+        name: "bag".into(),
+        icon: I::Bag,
+        item_type: ItemType::Container,
+        rarity: 20.0,
+        capacity: 8,
+        ..d()
+    },
+    ItemSpec {
+        name: "ration".into(),
+        icon: I::Ration,
+        item_type: Food(1000),
+        rarity: 4.0,
+        ..d()
+    },
+    ItemSpec {
+        name: "potion of healing".into(),
+        icon: I::Potion,
+        appearance: Some(Appearance::Potion),
+        item_type: UntargetedUsable(Heal),
+        rarity: 5.0,
+        ..d()
+    },
+    ItemSpec {
+        name: "potion of regeneration".into(),
+        icon: I::Potion,
+        appearance: Some(Appearance::Potion),
+        item_type: UntargetedUsable(Regeneration),
+        rarity: 10.0,
+        depth: 2,
+        ..d()
+    },
+    ItemSpec {
+        name: "potion of invisibility".into(),
+        icon: I::Potion,
+        appearance: Some(Appearance::Potion),
+        item_type: UntargetedUsable(Invisibility),
+        rarity: 10.0,
+        depth: 3,
+        ..d()
+    },
+    ItemSpec {
+        name: "scroll of magic mapping".into(),
+        icon: I::Scroll1,
+        appearance: Some(Appearance::Scroll),
+        item_type: UntargetedUsable(MagicMap),
+        rarity: 10.0,
+        depth: 1,
+        ..d()
+    },
+    ItemSpec {
+        name: "scroll of remove curse".into(),
+        icon: I::Scroll1,
+        appearance: Some(Appearance::Scroll),
+        item_type: UntargetedUsable(RemoveCurse),
+        rarity: 10.0,
+        depth: 1,
+        ..d()
+    },
     ItemSpec {
         name: "scroll of lightning".into(),
         icon: I::Scroll1,
+        appearance: Some(Appearance::Scroll),
         power: 1,
         item_type: UntargetedUsable(Lightning),
         ..d()
     },
+    ItemSpec {
+        name: "spellbook of healing".into(),
+        icon: I::Book,
+        item_type: Spellbook(Heal),
+        rarity: 5.0,
+        depth: 1,
+        ..d()
+    },
+    ItemSpec {
+        name: "spellbook of sleep".into(),
+        icon: I::Book,
+        item_type: Spellbook(Sleep),
+        rarity: 5.0,
+        depth: 2,
+        ..d()
+    },
+    ItemSpec {
+        name: "spellbook of lightning".into(),
+        icon: I::Book,
+        item_type: Spellbook(Lightning),
+        rarity: 3.0,
+        depth: 3,
+        ..d()
+    },
+    ItemSpec {
+        name: "spellbook of fireball".into(),
+        icon: I::Book,
+        item_type: Spellbook(Fireball),
+        rarity: 2.0,
+        depth: 5,
+        ..d()
+    },
+
+    // Furniture
+    ContainerSpec {
+        name: "chest".into(),
+        icon: I::Chest,
+        capacity: 12,
+    },
+
+    // Traps
+    TrapSpec {
+        name: "fire trap".into(),
+        effect: Fireball,
+    },
+    TrapSpec {
+        name: "sleeping gas trap".into(),
+        effect: Sleep,
+    },
 }
 
 /// String that's guaranteed to describe an entity spawn.
@@ -296,8 +637,12 @@ impl fmt::Display for EntitySpawn {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.0) }
 }
 
+/// Size of the player's mana pool at the start of the game.
+pub const PLAYER_MANA: i32 = 10;
+
 lazy_static! {
     pub static ref PLAYER_SPAWN: EntitySpawn = EntitySpawn("player".to_string());
+    pub static ref TORCH_SPAWN: EntitySpawn = EntitySpawn("torch".to_string());
 }
 
 #[derive(Debug)]
@@ -376,4 +721,27 @@ mod test {
         // Names not in database don't.
         assert!(ron::de::from_str::<EntitySpawn>(&"\"tyop txet\"".to_string()).is_err());
     }
+
+    #[test]
+    fn test_unidentified_names() {
+        use super::unidentified_name;
+
+        let wands = ["wand of fireball", "wand of confusion", "wand of sleep", "wand of fear"];
+        let names: Vec<String> = wands
+            .iter()
+            .map(|w| unidentified_name(1, w).unwrap())
+            .collect();
+
+        // Every kind looks different.
+        for (i, a) in names.iter().enumerate() {
+            assert!(a.ends_with("wand"));
+            assert!(names[i + 1..].iter().all(|b| a != b));
+        }
+
+        // Appearances stay the same for the seed.
+        assert_eq!(unidentified_name(1, wands[0]), Some(names[0].clone()));
+
+        // Things without an appearance class are recognized right away.
+        assert_eq!(unidentified_name(1, "sword"), None);
+    }
 }
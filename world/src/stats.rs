@@ -21,6 +21,9 @@ pub struct Stats {
     pub ranged_range: u32,
     /// Ranged attack power
     pub ranged_power: i32,
+    /// Radius of light shed around the entity. Zero means no light.
+    #[serde(default)]
+    pub light: u32,
 
     /// Bit flags for intrinsics
     pub intrinsics: u32,
@@ -41,6 +44,7 @@ impl Stats {
     pub fn armor(self, armor: i32) -> Stats { Stats { armor, ..self } }
     pub fn attack(self, attack: i32) -> Stats { Stats { attack, ..self } }
     pub fn defense(self, defense: i32) -> Stats { Stats { defense, ..self } }
+    pub fn light(self, light: u32) -> Stats { Stats { light, ..self } }
     pub fn ranged_range(self, ranged_range: u32) -> Stats {
         Stats {
             ranged_range,
@@ -75,6 +79,7 @@ impl Add<Stats> for Stats {
             // type dealie.
             ranged_range: self.ranged_range + other.ranged_range,
             ranged_power: self.ranged_power + other.ranged_power,
+            light: self.light + other.light,
             intrinsics: self.intrinsics | other.intrinsics,
         }
     }
@@ -91,4 +96,24 @@ pub enum Intrinsic {
     Hands,
     /// Explodes on death
     Deathsplosion,
+    /// Takes half damage from fire.
+    FireResistance,
+    /// Takes no damage from fire.
+    FireImmunity,
+    /// Takes double damage from fire.
+    FireVulnerability,
+    /// Takes half damage from cold.
+    ColdResistance,
+    /// Takes no damage from cold.
+    ColdImmunity,
+    /// Takes double damage from cold.
+    ColdVulnerability,
+    /// Takes half damage from electricity.
+    ElectricityResistance,
+    /// Takes no damage from electricity.
+    ElectricityImmunity,
+    /// Takes double damage from electricity.
+    ElectricityVulnerability,
+    /// Melee hits poison the target.
+    Poisonous,
 }
@@ -94,6 +94,7 @@ terrain_enum! {
     Rock:        TerrainData { name: "rock",      kind: Kind::Block,  form: Form::Blob,  map_chars: "*#",  is_regular: true,  color: 0xB84 },
     Door:        TerrainData { name: "door",      kind: Kind::Door,   form: Form::Wall,  map_chars: "|",   is_regular: true,  color: 0x842 },
     OpenDoor:    TerrainData { name: "open door", kind: Kind::Ground, form: Form::Wall,  map_chars: "",    is_regular: false, color: 0xFAF },
+    LockedDoor:  TerrainData { name: "locked door", kind: Kind::Door, form: Form::Wall,  map_chars: "",    is_regular: true,  color: 0x620 },
     Window:      TerrainData { name: "window",    kind: Kind::Window, form: Form::Wall,  map_chars: "+",   is_regular: true,  color: 0xBFF },
     Pillar:      TerrainData { name: "pillar",    kind: Kind::Block,  form: Form::Prop,  map_chars: "I",   is_regular: true,  color: 0xCCD },
     // TODO: Get rid of grass2, give render a coherent noise source for tiles and make it do the
@@ -144,10 +145,22 @@ impl Terrain {
 
     pub fn is_open(self) -> bool { self.kind() == Kind::Ground }
 
+    /// Closed door, can be walked through after it's opened.
     pub fn is_door(self) -> bool { self.kind() == Kind::Door }
 
+    pub fn is_locked_door(self) -> bool { self == Terrain::LockedDoor }
+
     pub fn is_luminous(self) -> bool { self.kind() == Kind::Magma }
 
+    /// Radius of light shed by luminous terrain.
+    pub fn light_radius(self) -> u32 {
+        if self.is_luminous() {
+            2
+        } else {
+            0
+        }
+    }
+
     pub fn is_wall(self) -> bool { self.form() == Form::Wall }
 
     pub fn is_hull(self) -> bool { self.form() == Form::Wall || self.form() == Form::Blob }
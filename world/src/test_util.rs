@@ -0,0 +1,63 @@
+//! Shared setup for tests that play around in a generated world.
+
+use calx::Dir6;
+use calx_ecs::Entity;
+use command::Command;
+use item::Slot;
+use location::Location;
+use mutate::Mutate;
+use query::Query;
+use spec::EntitySpawn;
+use std::str::FromStr;
+use terraform::Terraform;
+use terrain::Terrain;
+use world::World;
+use Distribution;
+
+/// Make a new world and return it with the player and the player's location.
+pub fn new_world() -> (World, Entity, Location) {
+    let world = World::new(1);
+    let player = world.player().unwrap();
+    let loc = world.location(player).unwrap();
+    (world, player, loc)
+}
+
+/// Spawn an entity by its spawn name at exactly the given location.
+///
+/// Items aren't spread out to free cells like with a regular spawn.
+pub fn spawn(world: &mut World, name: &str, loc: Location) -> Entity {
+    let loadout = EntitySpawn::from_str(name).unwrap().sample(world.rng());
+    let e = world.spawn(&loadout, loc);
+    world.set_entity_location(e, loc);
+    e
+}
+
+/// Spawn an item and have the player pick it up.
+pub fn give(world: &mut World, name: &str) -> Entity {
+    let loc = world.location(world.player().unwrap()).unwrap();
+    let item = spawn(world, name, loc);
+    world.take(item).unwrap();
+    item
+}
+
+/// Return the slot the entity is carrying the item in.
+pub fn slot_of(world: &World, e: Entity, item: Entity) -> Slot {
+    *Slot::iter()
+        .find(|&&slot| world.entity_equipped(e, slot) == Some(item))
+        .unwrap()
+}
+
+/// Lay a line of ground from origin towards a direction and return its locations.
+///
+/// The line has `len` cells after the origin.
+pub fn clear_line(world: &mut World, origin: Location, dir: Dir6, len: usize) -> Vec<Location> {
+    let mut line = vec![origin];
+    for i in 0..len {
+        let next = line[i].jump(&*world, dir);
+        line.push(next);
+    }
+    for &loc in &line {
+        world.set_terrain(loc, Terrain::Ground);
+    }
+    line
+}
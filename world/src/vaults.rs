@@ -27,6 +27,29 @@ vaults!{VAULTS,
       #....#
       ##++##
     ",
+    "
+      ##+##
+      #...#
+      #...#
+      ##=##
+      #.$.#
+      #####
+    ",
+    "
+      ##+##
+      #...#
+      +.&.+
+      #...#
+      ##+##
+    ",
+    "
+      ##+##
+      #.^.#
+      #*.*#
+      #.^.#
+      #.$.#
+      #####
+    ",
 }
 
 vaults!{ENTRANCES,
@@ -1,10 +1,11 @@
 use calx::HexFov;
-use fov::SphereVolumeFov;
+use fov::{NoiseFov, SphereVolumeFov};
 use location::Location;
 use std::iter::FromIterator;
 use world::World;
 
 /// `Volume` is a specific area of the game world.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Volume(pub Vec<Location>);
 
 impl Volume {
@@ -20,4 +21,14 @@ impl Volume {
             HexFov::new(SphereVolumeFov::new(w, radius, origin)).map(|(pos, a)| a.origin + pos),
         ))
     }
+
+    /// Construct the volume where a noise can be heard.
+    ///
+    /// Noise spreads through all portals and is stopped by terrain for which `blocks_walk` is
+    /// true.
+    pub fn noise(w: &World, origin: Location, loudness: u32) -> Volume {
+        Volume(Vec::from_iter(
+            HexFov::new(NoiseFov::new(w, loudness, origin)).map(|(pos, a)| a.origin + pos),
+        ))
+    }
 }
@@ -6,24 +6,31 @@ use event::Event;
 use flags::Flags;
 use fov::SightFov;
 use item::Slot;
+use light::LightMap;
 use location::{Location, Portal};
+use migrate::{self, LoadError};
 use mutate::Mutate;
+use overlay::Overlay;
+use pathing::{self, DijkstraMap, PATHING_RANGE};
 use query::Query;
+use replay::StateHasher;
 use ron;
 use spatial::{Place, Spatial};
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
+use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
 use std::iter::FromIterator;
+use std::rc::Rc;
 use std::slice;
 use terraform::{Terraform, TerrainQuery};
 use terrain::Terrain;
 use volume::Volume;
 use worldgen::Worldgen;
+use Cache;
 use Rng;
 
-pub const GAME_VERSION: &str = "0.1.0";
-
 Ecs! {
     desc: components::Desc,
     anim: components::Anim,
@@ -33,18 +40,24 @@ Ecs! {
     item: components::Item,
     stats: components::StatsComponent,
     status: components::Statuses,
+    nutrition: components::Nutrition,
+    container: components::Container,
+    experience: components::Experience,
+    mana: components::Mana,
+    trap: components::Trap,
 }
 
 /// Toplevel game state object.
 #[derive(Serialize, Deserialize)]
 pub struct World {
-    /// Game version. Not mutable in the slightest, but the simplest way to
-    /// get versioned save files is to just drop it here.
-    version: String,
+    /// Save file layout version. Used to find the migration steps needed to load older saves.
+    version: u32,
     /// Entity component system.
     ecs: Ecs,
     /// Static startup game world
     worldgen: Worldgen,
+    /// Changes to the startup game world
+    overlay: Overlay,
     /// Spatial index for game entities.
     spatial: Spatial,
     /// Global gamestate flags.
@@ -53,18 +66,31 @@ pub struct World {
     rng: Rng,
     /// Event queue
     events: Vec<Event>,
+    /// Pathing maps towards goal locations, keyed by whether doors can be opened.
+    #[serde(default)]
+    path_cache: Cache<RefCell<HashMap<(Location, bool), Rc<DijkstraMap>>>>,
+    /// Deathsplosions waiting to go off, `None` when no explosions are being processed.
+    #[serde(default)]
+    explosions: Cache<Option<VecDeque<(Entity, Location)>>>,
+    /// Light from light sources, `None` when it needs to be recomputed.
+    #[serde(default)]
+    light_cache: Cache<RefCell<Option<Rc<LightMap>>>>,
 }
 
 impl<'a> World {
     pub fn new(seed: u32) -> World {
         let mut ret = World {
-            version: GAME_VERSION.to_string(),
+            version: migrate::SAVE_VERSION,
             ecs: Ecs::new(),
             worldgen: Worldgen::new(seed),
+            overlay: Overlay::new(),
             spatial: Spatial::new(),
             flags: Flags::new(),
             rng: seeded_rng(&seed),
             events: Vec::new(),
+            path_cache: Cache::new(),
+            explosions: Cache::new(),
+            light_cache: Cache::new(),
         };
 
         // XXX: Clone to not run into borrow checker...
@@ -74,7 +100,7 @@ impl<'a> World {
             .cloned()
             .collect::<Vec<(Location, Loadout)>>()
         {
-            ret.spawn(&spawn, loc);
+            ret.spawn_generated(&spawn, loc);
         }
 
         // TODO non-lexical borrow
@@ -84,17 +110,10 @@ impl<'a> World {
         ret
     }
 
-    pub fn load<R: Read>(reader: &mut R) -> Result<World, Box<Error>> {
-        let ret: ron::de::Result<World> = ron::de::from_reader(reader);
-        if let Ok(ref x) = ret {
-            if x.version != GAME_VERSION {
-                panic!(
-                    "Save game version {} does not match current version {}",
-                    x.version, GAME_VERSION
-                );
-            }
-        }
-        Ok(ret?)
+    pub fn load<R: Read>(reader: &mut R) -> Result<World, LoadError> {
+        let mut doc = String::new();
+        reader.read_to_string(&mut doc)?;
+        migrate::load(doc)
     }
 
     pub fn save<W: Write>(&self, writer: &mut W) -> Result<(), Box<Error>> {
@@ -103,6 +122,33 @@ impl<'a> World {
         writeln!(writer, "{}", enc)?;
         Ok(())
     }
+
+    /// Hash of the parts of the game state that replays check for desyncs.
+    ///
+    /// Only covers things that are cheap to hash deterministically, not the whole world.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        self.flags.tick.hash(&mut hasher);
+        // XXX: The RNG doesn't expose its state, go through the serialization.
+        ron::ser::to_string(&self.rng)
+            .expect("Failed to serialize RNG")
+            .hash(&mut hasher);
+        for &e in self.entities() {
+            e.hash(&mut hasher);
+            self.location(e).hash(&mut hasher);
+            self.ecs.health.get(e).map(|h| h.wounds).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Drop the cached light map if moving the entity can change the light.
+    ///
+    /// Light sources are gathered around the player, so moving the player counts too.
+    fn invalidate_light(&self, e: Entity) {
+        if self.stats(e).light > 0 || self.player() == Some(e) {
+            *self.light_cache.borrow_mut() = None;
+        }
+    }
 }
 
 impl TerrainQuery for World {
@@ -112,19 +158,20 @@ impl TerrainQuery for World {
     }
 
     fn terrain(&self, loc: Location) -> Terrain {
-        let mut t = self.worldgen.get_terrain(loc);
-
-        if t == Terrain::Door && self.has_mobs(loc) {
-            // Standing in the doorway opens the door.
-            t = Terrain::OpenDoor;
-        }
-
-        t
+        self.overlay
+            .terrain(loc)
+            .unwrap_or_else(|| self.worldgen.get_terrain(loc))
     }
 
-    fn portal(&self, loc: Location) -> Option<Location> { self.worldgen.get_portal(loc) }
+    fn portal(&self, loc: Location) -> Option<Location> {
+        self.overlay
+            .portal(loc)
+            .unwrap_or_else(|| self.worldgen.get_portal(loc))
+    }
 
-    fn is_untouched(&self, _loc: Location) -> bool { unimplemented!() }
+    fn is_untouched(&self, loc: Location) -> bool {
+        !self.overlay.contains_terrain(loc) && self.worldgen.is_untouched(loc)
+    }
 }
 
 impl Query for World {
@@ -150,6 +197,10 @@ impl Query for World {
 
     fn rng_seed(&self) -> u32 { self.worldgen.seed() }
 
+    fn is_identified(&self, spawn_name: &str) -> bool {
+        self.flags.identified.contains(spawn_name)
+    }
+
     fn entities(&self) -> slice::Iter<Entity> { self.ecs.iter() }
 
     fn entities_at(&self, loc: Location) -> Vec<Entity> { self.spatial.entities_at(loc) }
@@ -166,9 +217,47 @@ impl Query for World {
         self.spatial.contains(parent, child)
     }
 
+    fn entity_container(&self, e: Entity) -> Option<Entity> {
+        match self.spatial.get(e) {
+            Some(Place::In(container, None)) => Some(container),
+            _ => None,
+        }
+    }
+
     fn sphere_volume(&self, origin: Location, radius: u32) -> Volume {
         Volume::sphere(self, origin, radius)
     }
+
+    fn noise_volume(&self, origin: Location, loudness: u32) -> Volume {
+        Volume::noise(self, origin, loudness)
+    }
+
+    fn path_map(&self, goal: Location, can_open_doors: bool) -> Rc<DijkstraMap> {
+        if let Some(map) = self.path_cache.borrow().get(&(goal, can_open_doors)) {
+            return map.clone();
+        }
+
+        let map = Rc::new(DijkstraMap::new(
+            self,
+            &[goal],
+            |loc| pathing::terrain_cost(self, loc, can_open_doors),
+            PATHING_RANGE,
+        ));
+        self.path_cache
+            .borrow_mut()
+            .insert((goal, can_open_doors), map.clone());
+        map
+    }
+
+    fn light_map(&self) -> Rc<LightMap> {
+        if let Some(ref map) = *self.light_cache.borrow() {
+            return map.clone();
+        }
+
+        let map = Rc::new(LightMap::new(self));
+        *self.light_cache.borrow_mut() = Some(map.clone());
+        map
+    }
 }
 
 impl Mutate for World {
@@ -182,28 +271,51 @@ impl Mutate for World {
         self.clean_dead();
         self.flags.tick += 1;
 
+        // Goals tend to move between ticks, don't let the cache fill up with stale maps.
+        self.path_cache.borrow_mut().clear();
+        // Light sources move around too.
+        *self.light_cache.borrow_mut() = None;
+
         // Dump events.
         let mut events = Vec::new();
         mem::swap(&mut self.events, &mut events);
         Ok(events)
     }
 
-    fn set_entity_location(&mut self, e: Entity, loc: Location) { self.spatial.insert_at(e, loc); }
+    fn set_entity_location(&mut self, e: Entity, loc: Location) {
+        self.invalidate_light(e);
+        self.spatial.insert_at(e, loc);
+    }
 
     fn equip_item(&mut self, e: Entity, parent: Entity, slot: Slot) {
+        self.invalidate_light(e);
         self.spatial.equip(e, parent, slot);
         self.rebuild_stats(parent);
     }
 
+    fn stow_item(&mut self, e: Entity, container: Entity) {
+        self.invalidate_light(e);
+        self.spatial.insert(e, Place::In(container, None));
+    }
+
     fn set_player(&mut self, player: Option<Entity>) { self.flags.player = player; }
 
     fn spawn(&mut self, loadout: &Loadout, loc: Location) -> Entity {
         let e = loadout.make(&mut self.ecs);
         self.place_entity(e, loc);
+
+        // Start with armor fully intact.
+        let armor = self.stats(e).armor;
+        if let Some(health) = self.ecs.health.get_mut(e) {
+            health.armor = armor;
+        }
         e
     }
 
-    fn kill_entity(&mut self, e: Entity) { self.spatial.remove(e); }
+    fn kill_entity(&mut self, e: Entity) {
+        self.invalidate_light(e);
+        self.spatial.remove(e);
+    }
 
     fn remove_entity(&mut self, e: Entity) { self.ecs.remove(e); }
 
@@ -247,6 +359,26 @@ impl Mutate for World {
 
     fn push_event(&mut self, event: Event) { self.events.push(event); }
 
+    fn queue_explosion(&mut self, e: Entity, loc: Location) -> bool {
+        let was_idle = self.explosions.is_none();
+        self.explosions
+            .get_or_insert_with(VecDeque::new)
+            .push_back((e, loc));
+        was_idle
+    }
+
+    fn next_explosion(&mut self) -> Option<(Entity, Location)> {
+        let ret = self.explosions.as_mut().and_then(|q| q.pop_front());
+        if ret.is_none() {
+            *self.explosions = None;
+        }
+        ret
+    }
+
+    fn mark_identified(&mut self, spawn_name: &str) {
+        self.flags.identified.insert(spawn_name.to_string());
+    }
+
     fn rng(&mut self) -> &mut Rng { &mut self.rng }
 
     fn ecs_mut(&mut self) -> &mut Ecs { &mut self.ecs }
@@ -255,15 +387,190 @@ impl Mutate for World {
 impl Command for World {}
 
 impl Terraform for World {
-    fn set_terrain(&mut self, _loc: Location, _terrain: Terrain) {
-        unimplemented!();
+    fn set_terrain(&mut self, loc: Location, terrain: Terrain) {
+        self.path_cache.borrow_mut().clear();
+        *self.light_cache.borrow_mut() = None;
+        if terrain == Terrain::Empty || terrain == self.worldgen.get_terrain(loc) {
+            self.overlay.clear_terrain(loc);
+        } else {
+            self.overlay.set_terrain(loc, terrain);
+        }
     }
 
-    fn set_portal(&mut self, _loc: Location, _portal: Portal) {
-        unimplemented!();
+    fn set_portal(&mut self, loc: Location, portal: Portal) {
+        // Don't make chains of portals, point directly to the final destination instead.
+        let destination = self.portal(loc + portal).unwrap_or(loc + portal);
+
+        if destination == loc {
+            return;
+        }
+
+        self.path_cache.borrow_mut().clear();
+        *self.light_cache.borrow_mut() = None;
+
+        if self.worldgen.get_portal(loc) == Some(destination) {
+            self.overlay.clear_portal(loc);
+        } else {
+            self.overlay
+                .set_portal(loc, Some(Portal::new(loc, destination)));
+        }
     }
 
-    fn remove_portal(&mut self, _loc: Location) {
-        unimplemented!();
+    fn remove_portal(&mut self, loc: Location) {
+        self.path_cache.borrow_mut().clear();
+        *self.light_cache.borrow_mut() = None;
+        if self.worldgen.get_portal(loc).is_some() {
+            self.overlay.set_portal(loc, None);
+        } else {
+            self.overlay.clear_portal(loc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::World;
+    use calx::Dir6;
+    use command::Command;
+    use location::{Location, Portal};
+    use mutate::Mutate;
+    use query::Query;
+    use terraform::{Terraform, TerrainQuery};
+    use terrain::Terrain;
+    use test_util::{give, new_world, slot_of, spawn};
+
+    #[test]
+    fn test_terrain_overlay_persists() {
+        let mut world = World::new(1);
+        let loc = Location::new(1, 2, 3);
+        let portal_loc = Location::new(3, 2, 1);
+        let destination = Location::new(10, 10, 2);
+
+        world.set_terrain(loc, Terrain::Magma);
+        world.set_portal(portal_loc, Portal::new(portal_loc, destination));
+        assert_eq!(world.terrain(loc), Terrain::Magma);
+        assert_eq!(world.portal(portal_loc), Some(destination));
+
+        let mut save = Vec::new();
+        world.save(&mut save).unwrap();
+        let mut world = World::load(&mut &save[..]).unwrap();
+
+        assert_eq!(world.terrain(loc), Terrain::Magma);
+        assert_eq!(world.portal(portal_loc), Some(destination));
+
+        world.remove_portal(portal_loc);
+        assert_eq!(world.portal(portal_loc), None);
+    }
+
+    #[test]
+    fn test_door_state_persists() {
+        let (mut world, _, loc) = new_world();
+        let door = loc.jump(&world, Dir6::North);
+        world.set_terrain(door, Terrain::Door);
+
+        world.open(Dir6::North).unwrap();
+        assert_eq!(world.terrain(door), Terrain::OpenDoor);
+
+        let mut save = Vec::new();
+        world.save(&mut save).unwrap();
+        let mut world = World::load(&mut &save[..]).unwrap();
+        assert_eq!(world.terrain(door), Terrain::OpenDoor);
+
+        world.close(Dir6::North).unwrap();
+        assert_eq!(world.terrain(door), Terrain::Door);
+
+        world.set_terrain(door, Terrain::LockedDoor);
+        assert!(world.open(Dir6::North).is_err());
+    }
+
+    #[test]
+    fn test_container_contents_persist() {
+        let (mut world, player, _) = new_world();
+        let bag = give(&mut world, "bag");
+        let ration = give(&mut world, "ration");
+
+        let bag_slot = slot_of(&world, player, bag);
+        assert!(world.put_in(bag_slot, bag).is_err());
+        let ration_slot = slot_of(&world, player, ration);
+        world.put_in(ration_slot, bag).unwrap();
+        assert_eq!(world.contents(bag), vec![ration]);
+        assert!(world.entity_equipped(player, ration_slot).is_none());
+
+        let mut save = Vec::new();
+        world.save(&mut save).unwrap();
+        let mut world = World::load(&mut &save[..]).unwrap();
+
+        assert_eq!(world.contents(bag), vec![ration]);
+        assert_eq!(world.entity_container(ration), Some(bag));
+        assert!(world.entity_contains(player, ration));
+
+        world.take_out(ration).unwrap();
+        assert!(world.contents(bag).is_empty());
+        assert_eq!(world.entity_container(ration), None);
+    }
+
+    #[test]
+    fn test_light_sources() {
+        let (mut world, player, loc) = new_world();
+
+        // The player starts out with a torch.
+        assert!(world.stats(player).light > 0);
+        assert_eq!(world.light_map().get(loc), 1.0);
+
+        let torch = world
+            .entities_in(player)
+            .into_iter()
+            .find(|&e| world.spawn_name(e) == Some("torch"))
+            .unwrap();
+        let slot = slot_of(&world, player, torch);
+        world.drop(slot).unwrap();
+        assert_eq!(world.stats(player).light, 0);
+
+        // A torch on the floor still lights up its surroundings, less so further away.
+        let torch_loc = world.location(torch).unwrap();
+        assert_eq!(world.light_map().get(torch_loc), 1.0);
+        let next_loc = torch_loc.jump(&world, Dir6::North);
+        assert!(world.light_map().get(next_loc) < 1.0);
+
+        // Changing the terrain invalidates the light map.
+        let behind = next_loc.jump(&world, Dir6::North);
+        world.set_terrain(next_loc, Terrain::Ground);
+        world.set_terrain(behind, Terrain::Ground);
+        assert!(world.light_map().get(behind) > 0.0);
+        world.set_terrain(next_loc, Terrain::Wall);
+        assert_eq!(world.light_map().get(behind), 0.0);
+
+        // Carrying the torch away takes the light along without waiting for the next tick.
+        world.equip_item(torch, player, slot);
+        let far_loc = Location::new(loc.x + 20, loc.y, loc.z);
+        world.set_entity_location(player, far_loc);
+        assert_eq!(world.light_map().get(torch_loc), 0.0);
+        assert_eq!(world.light_map().get(far_loc), 1.0);
+    }
+
+    #[test]
+    fn test_light_across_portal() {
+        let (mut world, player, loc) = new_world();
+
+        // Leave the player in the dark.
+        for e in world.entities_in(player) {
+            world.kill_entity(e);
+        }
+        world.rebuild_stats(player);
+        assert_eq!(world.stats(player).light, 0);
+
+        // Open a window to another level next to the player. `set_terrain` treats void as undoing
+        // changes, so put it in the overlay directly.
+        let portal_loc = loc + Dir6::North;
+        let destination = Location::new(loc.x, loc.y, loc.z + 1);
+        world.overlay.set_terrain(portal_loc, Terrain::Empty);
+        world.set_portal(portal_loc, Portal::new(portal_loc, destination));
+        let torch_loc = destination + Dir6::North;
+        world.set_terrain(destination, Terrain::Ground);
+        world.set_terrain(torch_loc, Terrain::Ground);
+
+        spawn(&mut world, "torch", torch_loc);
+        assert_eq!(world.light_map().get(torch_loc), 1.0);
+        assert!(world.light_map().get(destination) > 0.0);
     }
 }
@@ -96,6 +96,9 @@ impl Worldgen {
 
     fn default_terrain(&self, _loc: Location) -> Terrain { Terrain::Rock }
 
+    /// Return whether map generation left the location at default terrain.
+    pub fn is_untouched(&self, loc: Location) -> bool { !self.terrain.contains_key(&loc) }
+
     pub fn get_portal(&self, loc: Location) -> Option<Location> {
         self.portals.get(&loc).map(|&p| loc + p)
     }